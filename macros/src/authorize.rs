@@ -0,0 +1,117 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{
+    parse_quote, punctuated::Punctuated, Attribute, Error, Expr, FnArg, Ident, ImplItem,
+    ImplItemFn, ItemImpl, Lit, Meta, Pat, Token, Type,
+};
+
+/// `UserType` variants, from the lowest to the highest privilege
+const ROLES: &[&str] = &["Customer", "Worker", "Manager"];
+
+/// Parsed `#[role(Worker)]` or `#[role(Worker, owner = "expr")]`
+struct Role {
+    role: Ident,
+    /// `Into<Option<u32>>` user id let through regardless of its role
+    owner: Option<Expr>,
+}
+
+pub fn authorize_impl(input: TokenStream) -> syn::Result<TokenStream> {
+    let mut item = syn::parse2::<ItemImpl>(input)?;
+    for item in &mut item.items {
+        let ImplItem::Fn(method) = item else {
+            continue;
+        };
+        if !method.attrs.iter().any(|attr| attr.path().is_ident("oai")) {
+            continue;
+        }
+        let role = take_role(&mut method.attrs)?;
+        let doc = match (role, auth_param(method)) {
+            (Some(Role { role, owner }), Some(auth)) => {
+                let role_ty = quote! { crate::models::user::UserType::#role };
+                let (check, doc) = match owner {
+                    Some(owner) => (
+                        quote! { #auth.require_owner(#role_ty, #owner)?; },
+                        format!(" Required role: {role}, or the owning user"),
+                    ),
+                    None => (
+                        quote! { #auth.require(#role_ty)?; },
+                        format!(" Required role: {role}"),
+                    ),
+                };
+                method.block.stmts.insert(0, parse_quote! { #check });
+                doc
+            }
+            (None, None) => " Required role: none".to_owned(),
+            (Some(Role { role, .. }), None) => {
+                return Err(Error::new_spanned(
+                    role,
+                    "Role attr requires an Auth parameter",
+                ))
+            }
+            (None, Some(_)) => {
+                return Err(Error::new_spanned(
+                    &method.sig.ident,
+                    "Operations taking Auth must declare #[role(...)]",
+                ))
+            }
+        };
+        method.attrs.push(parse_quote! { #[doc = ""] });
+        method.attrs.push(parse_quote! { #[doc = #doc] });
+    }
+    Ok(quote! { #item })
+}
+
+/// Removes the `#[role(...)]` attribute of an operation and parses it
+fn take_role(attrs: &mut Vec<Attribute>) -> syn::Result<Option<Role>> {
+    let Some(index) = attrs.iter().position(|attr| attr.path().is_ident("role")) else {
+        return Ok(None);
+    };
+    let attr = attrs.remove(index);
+    let args = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+    let mut args = args.into_iter();
+    let role = match args.next() {
+        Some(Meta::Path(path)) => path
+            .get_ident()
+            .filter(|ident| ROLES.iter().any(|role| ident == role))
+            .cloned()
+            .ok_or_else(|| Error::new_spanned(&path, "Role must be Customer, Worker or Manager"))?,
+        _ => {
+            return Err(Error::new_spanned(
+                &attr,
+                "Role attr requires a role, e.g. #[role(Worker)]",
+            ))
+        }
+    };
+    let mut owner = None;
+    for arg in args {
+        match arg {
+            Meta::NameValue(arg) if arg.path.is_ident("owner") && owner.is_none() => {
+                let Expr::Lit(lit) = &arg.value else {
+                    return Err(Error::new_spanned(&arg.value, "Owner must be a string"));
+                };
+                let Lit::Str(expr) = &lit.lit else {
+                    return Err(Error::new_spanned(lit, "Owner must be a string"));
+                };
+                owner = Some(expr.parse::<Expr>()?);
+            }
+            arg => return Err(Error::new_spanned(arg, "Unknown role argument")),
+        }
+    }
+    Ok(Some(Role { role, owner }))
+}
+
+/// Binding of the `Auth` parameter of an operation
+fn auth_param(method: &ImplItemFn) -> Option<&Ident> {
+    method.sig.inputs.iter().find_map(|input| {
+        let FnArg::Typed(input) = input else {
+            return None;
+        };
+        let Type::Path(ty) = &*input.ty else {
+            return None;
+        };
+        let Pat::Ident(pat) = &*input.pat else {
+            return None;
+        };
+        (ty.path.segments.last()?.ident == "Auth").then_some(&pat.ident)
+    })
+}
@@ -38,6 +38,50 @@ pub fn derive_json_parameters_impl(input: TokenStream) -> syn::Result<TokenStrea
             }
         })
         .collect::<Vec<_>>();
+    let fields = variants
+        .iter()
+        .filter(|variant| match &variant.fields {
+            Fields::Named(fields) => fields
+                .named
+                .iter()
+                .any(|field| field.ident.as_ref().is_some_and(|ident| ident == "field")),
+            _ => false,
+        })
+        .map(|variant| {
+            let ident = &variant.ident;
+            quote! { Self::#ident { field, .. } => Some(field.to_string()), }
+        })
+        .collect::<Vec<_>>();
+
+    let named_parameters = variants
+        .iter()
+        .map(|variant| {
+            let ident = &variant.ident;
+            match &variant.fields {
+                Fields::Named(fields) => {
+                    let fields = fields
+                        .named
+                        .iter()
+                        .map(|field| field.ident.as_ref())
+                        .collect::<Vec<_>>();
+                    let names = fields.iter().map(|field| field.map(ToString::to_string));
+                    quote_spanned! { variant.span() =>
+                        Self::#ident {#(#fields,)*} => vec![#((#names, #fields.to_json().unwrap_or_default()),)*],
+                    }
+                }
+                Fields::Unnamed(fields) => {
+                    let fields: Vec<_> = (0..fields.unnamed.len())
+                        .map(|i| format_ident!("f{i}"))
+                        .collect();
+                    let names = (0..fields.len()).map(|i| i.to_string());
+                    quote_spanned! { variant.span() =>
+                        Self::#ident (#(#fields,)*) => vec![#((#names, #fields.to_json().unwrap_or_default()),)*],
+                    }
+                }
+                Fields::Unit => quote! { Self::#ident => Vec::new(), },
+            }
+        })
+        .collect::<Vec<_>>();
 
     Ok(quote! {
         impl #ident {
@@ -47,6 +91,22 @@ pub fn derive_json_parameters_impl(input: TokenStream) -> syn::Result<TokenStrea
                     #(#parameters)*
                 }
             }
+
+            /// Parameters keyed by field name, or by position for tuple variants
+            pub fn named_parameters(&self) -> Vec<(&'static str, serde_json::Value)> {
+                use poem_openapi::types::ToJSON;
+                match self {
+                    #(#named_parameters)*
+                }
+            }
+
+            /// Name of the offending field, for variants with a `field`
+            pub fn field(&self) -> Option<String> {
+                match self {
+                    #(#fields)*
+                    _ => None,
+                }
+            }
         }
     })
 }
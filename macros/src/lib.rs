@@ -1,10 +1,12 @@
 extern crate proc_macro;
 
+mod authorize;
 mod display_upper_snake;
 mod json_parameters;
 mod response_enum;
 mod validation;
 
+use authorize::authorize_impl;
 use display_upper_snake::derive_display_upper_snake_impl;
 use json_parameters::derive_json_parameters_impl;
 use proc_macro::TokenStream;
@@ -18,7 +20,7 @@ pub fn derive_display_upper_snake(input: TokenStream) -> TokenStream {
     )
 }
 
-#[proc_macro_derive(ResponseEnum)]
+#[proc_macro_derive(ResponseEnum, attributes(status))]
 pub fn derive_response_enum(input: TokenStream) -> TokenStream {
     TokenStream::from(
         derive_response_enum_impl(input.into()).unwrap_or_else(|err| err.to_compile_error()),
@@ -38,3 +40,10 @@ pub fn derive_validation(input: TokenStream) -> TokenStream {
         derive_validation_impl(input.into()).unwrap_or_else(|err| err.to_compile_error()),
     )
 }
+
+/// Enforces and documents the `#[role(...)]` of every operation of an `#[OpenApi]` impl, which
+/// it has to precede
+#[proc_macro_attribute]
+pub fn authorize(_args: TokenStream, input: TokenStream) -> TokenStream {
+    TokenStream::from(authorize_impl(input.into()).unwrap_or_else(|err| err.to_compile_error()))
+}
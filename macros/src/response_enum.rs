@@ -1,7 +1,7 @@
 use convert_case::{Case, Casing};
 use proc_macro2::{Span, TokenStream};
 use quote::quote;
-use syn::{Data, DeriveInput, Type};
+use syn::{Data, DeriveInput, LitInt};
 
 pub fn derive_response_enum_impl(input: TokenStream) -> syn::Result<TokenStream> {
     let input = syn::parse2::<DeriveInput>(input).unwrap();
@@ -13,74 +13,156 @@ pub fn derive_response_enum_impl(input: TokenStream) -> syn::Result<TokenStream>
 
     let ident = input.ident;
     let ident_str = ident.to_string();
-    let parameters = variants
-        .iter()
-        .map(|variant| {
-            let code = variant.ident.to_string().to_case(Case::UpperSnake);
-            match &variant.fields {
-                syn::Fields::Named(fields) => {
-                    let params = fields
-                        .named
-                        .iter()
-                        .map(|f| f.ident.as_ref().map_or("?".to_owned(), |i| i.to_string()))
-                        .collect::<Vec<_>>()
-                        .join(",");
-                    quote! { serde_json::Value::String(format!("{}({})", #code, #params)), }
-                }
-                syn::Fields::Unnamed(fields) => {
-                    let params = fields
-                        .unnamed
-                        .iter()
-                        .map(|f| {
-                            let mut ty = &f.ty;
-                            if let Type::Reference(r) = ty {
-                                ty = &r.elem;
-                            }
-                            if let Type::Path(p) = ty {
-                                if !p.path.segments.is_empty() {
-                                    let mut type_string = p.path.segments[0].ident.to_string();
-                                    if &type_string == "str" {
-                                        type_string = "String".to_string();
-                                    }
-                                    return type_string;
-                                }
-                            }
-                            "?".to_owned()
-                        })
-                        .collect::<Vec<_>>()
-                        .join(",");
-                    quote! { serde_json::Value::String(format!("{}({})", #code, #params)), }
+    let schema_ref =
+        |name: &str| quote! { poem_openapi::registry::MetaSchemaRef::Reference(#name.to_owned()) };
+    let mut registers = Vec::new();
+    let mut one_of = Vec::new();
+    let mut mapping = Vec::new();
+    let mut markers = Vec::new();
+    let mut statuses = Vec::new();
+    for variant in &variants {
+        let variant_ident = &variant.ident;
+        let code = variant.ident.to_string().to_case(Case::UpperSnake);
+        let status = status(variant)?;
+        let error_schema = format!("{}Error", variant.ident);
+        let parameters_schema = format!("{}Parameters", variant.ident);
+        let names = variant
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(i, f)| f.ident.as_ref().map_or(i.to_string(), ToString::to_string))
+            .collect::<Vec<_>>();
+        let types = variant.fields.iter().map(|f| &f.ty).collect::<Vec<_>>();
+
+        let mut properties = vec![quote! {
+            ("code", poem_openapi::registry::MetaSchemaRef::Inline(Box::new(
+                poem_openapi::registry::MetaSchema {
+                    enum_items: vec![serde_json::Value::String(#code.to_owned())],
+                    ..poem_openapi::registry::MetaSchema::new("string")
+                },
+            ))),
+        }];
+        if names.iter().any(|name| name == "field") {
+            properties.push(quote! {
+                ("field", <String as poem_openapi::types::Type>::schema_ref()),
+            });
+        }
+        if !types.is_empty() {
+            let parameters_ref = schema_ref(&parameters_schema);
+            properties.push(quote! {
+                ("parameters", poem_openapi::registry::MetaSchemaRef::Inline(Box::new(
+                    poem_openapi::registry::MetaSchema {
+                        description: Some(concat!("Positional parameters of ", #code)),
+                        items: Some(Box::new(
+                            <serde_json::Value as poem_openapi::types::Type>::schema_ref(),
+                        )),
+                        ..poem_openapi::registry::MetaSchema::new("array")
+                    },
+                ))),
+                ("namedParameters", #parameters_ref),
+            });
+            registers.push(quote! {
+                registry.create_schema::<Self, _>(#parameters_schema.to_owned(), |registry| {
+                    #(<#types as poem_openapi::types::Type>::register(registry);)*
+                    let mut required = Vec::new();
+                    #(
+                        if <#types as poem_openapi::types::Type>::IS_REQUIRED {
+                            required.push(#names);
+                        }
+                    )*
+                    poem_openapi::registry::MetaSchema {
+                        description: Some(concat!("Named parameters of ", #code)),
+                        properties: vec![
+                            #((#names, <#types as poem_openapi::types::Type>::schema_ref()),)*
+                        ],
+                        required,
+                        ..poem_openapi::registry::MetaSchema::new("object")
+                    }
+                });
+            });
+        }
+        let union_ref = schema_ref(&ident_str);
+        registers.push(quote! {
+            registry.create_schema::<Self, _>(#error_schema.to_owned(), |_| {
+                poem_openapi::registry::MetaSchema {
+                    properties: vec![
+                        #(#properties)*
+                        ("message", <String as poem_openapi::types::Type>::schema_ref()),
+                        ("requestId", <String as poem_openapi::types::Type>::schema_ref()),
+                        ("errors", poem_openapi::registry::MetaSchemaRef::Inline(Box::new(
+                            poem_openapi::registry::MetaSchema {
+                                items: Some(Box::new(#union_ref)),
+                                ..poem_openapi::registry::MetaSchema::new("array")
+                            },
+                        ))),
+                    ],
+                    required: vec!["code"],
+                    ..poem_openapi::registry::MetaSchema::new("object")
                 }
-                syn::Fields::Unit => {
-                    quote! { serde_json::Value::String(#code.to_owned()), }
+            });
+        });
+        one_of.push(schema_ref(&error_schema));
+        let target = format!("#/components/schemas/{error_schema}");
+        mapping.push(quote! { (#code.to_owned(), #target.to_owned()), });
+        statuses.push(quote! { Self::#variant_ident { .. } => #status, });
+        let doc = format!("`{code}` error, answered with status {status}");
+        markers.push(quote! {
+            #[doc = #doc]
+            pub struct #variant_ident;
+
+            impl crate::api::api_error::ErrorCodes for #variant_ident {
+                fn codes() -> Vec<crate::api::api_error::ErrorCode> {
+                    vec![crate::api::api_error::ErrorCode {
+                        code: #code,
+                        status: #status,
+                        schema: #error_schema,
+                    }]
                 }
             }
-        })
-        .collect::<Vec<_>>();
+        });
+    }
+    let variant_idents = variants.iter().map(|variant| &variant.ident);
+
     Ok(quote! {
+        /// Marker types of the error codes, listed in `ApiError` to document operations
+        pub mod codes {
+            #(#markers)*
+        }
+
+        impl #ident {
+            /// Status declared with `#[status(N)]`, 400 without it
+            pub fn status(&self) -> poem::http::StatusCode {
+                let status = match self {
+                    #(#statuses)*
+                };
+                poem::http::StatusCode::from_u16(status).expect("status checked by ResponseEnum derive")
+            }
+        }
+
+        impl crate::api::api_error::ErrorCodes for #ident {
+            fn codes() -> Vec<crate::api::api_error::ErrorCode> {
+                let mut codes = Vec::new();
+                #(codes.extend(<codes::#variant_idents as crate::api::api_error::ErrorCodes>::codes());)*
+                codes
+            }
+        }
+
         impl poem_openapi::ApiResponse for #ident {
             fn meta() -> poem_openapi::registry::MetaResponses {
-                poem_openapi::registry::MetaResponses {
-                    responses: vec![poem_openapi::registry::MetaResponse {
-                        description: concat!("Marker endpoint to generate ", #ident_str, " Schema"),
-                        status: Some(200),
-                        content: vec![poem_openapi::registry::MetaMediaType {
-                            content_type: "text/plain; charset=utf-8",
-                            schema: poem_openapi::registry::MetaSchemaRef::Reference(#ident_str.to_owned()),
-                        }],
-                        headers: vec![],
-                    }],
-                }
+                crate::api::api_error::responses(<Self as crate::api::api_error::ErrorCodes>::codes())
             }
 
             fn register(registry: &mut poem_openapi::registry::Registry) {
-                registry.create_schema::<Self, _>("ValidationError".to_owned(), |_| {
+                registry.create_schema::<Self, _>(#ident_str.to_owned(), |registry| {
+                    #(#registers)*
                     poem_openapi::registry::MetaSchema {
-                        description: Some("Available codes with parameters"),
-                        enum_items: vec![
-                            #(#parameters)*
-                        ],
-                        ..poem_openapi::registry::MetaSchema::new("string")
+                        description: Some("Error body, discriminated by `code`"),
+                        one_of: vec![#(#one_of,)*],
+                        discriminator: Some(poem_openapi::registry::MetaDiscriminatorObject {
+                            property_name: "code",
+                            mapping: vec![#(#mapping)*],
+                        }),
+                        ..poem_openapi::registry::MetaSchema::new("")
                     }
                 });
             }
@@ -88,8 +170,27 @@ pub fn derive_response_enum_impl(input: TokenStream) -> syn::Result<TokenStream>
 
         impl IntoResponse for #ident {
             fn into_response(self) -> Response {
-                poem::http::StatusCode::NOT_FOUND.into_response()
+                poem::error::ResponseError::as_response(&self)
             }
         }
     })
 }
+
+/// Status of `#[status(N)]` on the variant, 400 without it
+fn status(variant: &syn::Variant) -> syn::Result<u16> {
+    let Some(attr) = variant
+        .attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("status"))
+    else {
+        return Ok(400);
+    };
+    let status = attr.parse_args::<LitInt>()?.base10_parse::<u16>()?;
+    if !(400..600).contains(&status) {
+        return Err(syn::Error::new_spanned(
+            attr,
+            "Status attr must be an error status (400-599)",
+        ));
+    }
+    Ok(status)
+}
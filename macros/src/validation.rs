@@ -1,12 +1,21 @@
+use convert_case::{Case, Casing};
 use darling::{
     ast::Data,
-    util::{Ignored, SpannedValue},
-    FromDeriveInput, FromField,
+    util::{Flag, Ignored, PathList, SpannedValue},
+    FromDeriveInput, FromField, FromMeta,
 };
 use proc_macro2::{Span, TokenStream};
 use quote::quote;
 use regex::Regex;
-use syn::{DeriveInput, Error, Ident, Path, Type, TypePath};
+use std::ops::Deref;
+use syn::{
+    spanned::Spanned, DeriveInput, Error, Expr, ExprLit, ExprUnary, GenericArgument, Ident, Lit,
+    LitStr, Meta, Path, PathArguments, Type, UnOp,
+};
+
+const NUMERIC_TYPES: &[&str] = &[
+    "u8", "u16", "u32", "u64", "usize", "i8", "i16", "i32", "i64", "isize", "f32", "f64",
+];
 
 #[derive(FromField)]
 #[darling(attributes(val))]
@@ -20,6 +29,73 @@ struct ValidationField {
     length: Option<Path>,
     #[darling(default)]
     pattern: Option<SpannedValue<String>>,
+    #[darling(default)]
+    range: Option<AttrSpanned<RangeArgs>>,
+    email: Flag,
+    phone: Flag,
+    luhn: Flag,
+    #[darling(default)]
+    one_of: Option<AttrSpanned<Vec<LitStr>>>,
+    not_blank: Flag,
+    past: Flag,
+    future: Flag,
+    #[darling(default)]
+    min_age: Option<SpannedValue<u32>>,
+    /// `fn(&str, &T) -> Result<(), ValidationError>`
+    #[darling(default)]
+    custom: Option<Hook>,
+    /// `fn(&T) -> Result<(), ValidationError>`, run with the struct level rules
+    #[darling(default)]
+    rule: Option<Hook>,
+    /// `async fn(&T, &ValidationContext) -> anyhow::Result<Option<ValidationError>>`, run with
+    /// the struct level checks
+    #[darling(default)]
+    check: Option<Hook>,
+}
+
+/// `custom`, `rule` or `check` function with the codes it may fail with, e.g.
+/// `rule(path = "not_expired", codes(CardExpired))`
+#[derive(FromMeta)]
+struct Hook {
+    path: Path,
+    codes: PathList,
+}
+
+/// List attr spanned by its name, `SpannedValue` of an empty list points at the derive
+struct AttrSpanned<T> {
+    value: T,
+    span: Span,
+}
+
+impl<T> AttrSpanned<T> {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl<T> Deref for AttrSpanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: FromMeta> FromMeta for AttrSpanned<T> {
+    fn from_meta(item: &Meta) -> darling::Result<Self> {
+        Ok(Self {
+            value: T::from_meta(item)?,
+            span: item.path().span(),
+        })
+    }
+}
+
+#[derive(FromMeta)]
+struct RangeArgs {
+    #[darling(default)]
+    min: Option<Expr>,
+    #[darling(default)]
+    max: Option<Expr>,
 }
 
 #[derive(FromDeriveInput)]
@@ -32,6 +108,21 @@ struct ValidationInput {
     trim: bool,
     #[darling(default)]
     length: Option<Path>,
+    /// Struct level `fn(&Self) -> Result<(), ValidationError>`
+    #[darling(multiple)]
+    rule: Vec<Hook>,
+    /// `async fn(&Self, &ValidationContext) -> anyhow::Result<Option<ValidationError>>`
+    #[darling(multiple)]
+    check: Vec<Hook>,
+}
+
+/// Value category of a field, looking through `Option`
+#[derive(PartialEq)]
+enum Kind {
+    String,
+    Numeric,
+    Date,
+    Other,
 }
 
 pub fn derive_validation_impl(input: TokenStream) -> syn::Result<TokenStream> {
@@ -48,21 +139,63 @@ pub fn derive_validation_impl(input: TokenStream) -> syn::Result<TokenStream> {
             ))
         }
     };
-    let string_type = get_type("String");
 
+    let error = quote! { crate::api::validation_error::ValidationError };
+    let rules = quote! { crate::api::validation_rules };
     let mut trims = Vec::new();
-    let mut lengths = Vec::new();
-    let mut patterns = Vec::new();
+    let mut checks = Vec::new();
+    let mut field_rules = Vec::new();
+    let mut field_checks = Vec::new();
+    let mut schema = Vec::new();
+    let mut codes = Vec::new();
+    let mut hook_codes = Vec::new();
+    for hook in input.rule.iter().chain(&input.check) {
+        hook_codes.extend(hook_codes_of(hook)?);
+    }
     for field in &args.fields {
         let ident = field
             .ident
             .as_ref()
             .ok_or_else(|| Error::new_spanned(ident, "All fields must be named"))?;
-        let ident_str = ident.to_string();
+        let name = ident.to_string().to_case(Case::Camel);
+        let (optional, inner) = unwrap_option(&field.ty);
+        let kind = kind(inner);
+        let value = value_expr(ident, optional);
+        // String values are passed to hooks as `&str`
+        let arg = if kind == Kind::String {
+            quote! { value.as_str() }
+        } else {
+            quote! { value }
+        };
+        let mut keywords = Vec::new();
+        let require = |required: Kind, span: Span, attr: &str| {
+            if kind == required {
+                return Ok(());
+            }
+            let expected = match required {
+                Kind::String => "String",
+                Kind::Numeric => "numeric",
+                Kind::Date => "NaiveDate",
+                Kind::Other => "any",
+            };
+            Err(Error::new(
+                span,
+                format!("{attr} attr may only be applied on {expected} field"),
+            ))
+        };
+
         if field.trim || input.trim {
-            if field.ty == string_type {
-                trims.push(quote! {
-                    trim_in_place::TrimInPlace::trim_in_place(&mut self.#ident);
+            if kind == Kind::String {
+                trims.push(if optional {
+                    quote! {
+                        if let Some(value) = &mut self.#ident {
+                            trim_in_place::TrimInPlace::trim_in_place(value);
+                        }
+                    }
+                } else {
+                    quote! {
+                        trim_in_place::TrimInPlace::trim_in_place(&mut self.#ident);
+                    }
                 })
             } else if field.trim {
                 return Err(Error::new_spanned(
@@ -72,17 +205,37 @@ pub fn derive_validation_impl(input: TokenStream) -> syn::Result<TokenStream> {
             }
         }
 
-        if let Some(length) = field.length.as_ref().or(input.length.as_ref()) {
-            if field.ty == string_type {
-                lengths.push(quote!{
-                    let (min, max) = #length();
-                    if self.#ident.len() < min {
-                        return Err(crate::api::validation_error::ValidationError::MinLength { field: #ident_str, min }.into());
+        if field.not_blank.is_present() {
+            require(Kind::String, field.not_blank.span(), "Not blank")?;
+            codes.push("NotBlank");
+            checks.push(quote! {
+                if let Some(value) = #value {
+                    if value.trim().is_empty() {
+                        errors.push(#error::NotBlank { field: #name });
                     }
-                    if self.#ident.len() > max {
-                        return Err(crate::api::validation_error::ValidationError::MaxLength { field: #ident_str, max }.into());
+                }
+            });
+        }
+
+        if let Some(length) = field.length.as_ref().or(input.length.as_ref()) {
+            if kind == Kind::String {
+                codes.extend(["MinLength", "MaxLength"]);
+                checks.push(quote! {
+                    if let Some(value) = #value {
+                        let (min, max) = #length();
+                        if value.len() < min {
+                            errors.push(#error::MinLength { field: #name, min });
+                        }
+                        if value.len() > max {
+                            errors.push(#error::MaxLength { field: #name, max });
+                        }
                     }
                 });
+                keywords.push(quote! {
+                    let (min, max) = #length();
+                    property.min_length = Some(min);
+                    property.max_length = Some(max);
+                });
             } else if field.length.is_some() {
                 return Err(Error::new_spanned(
                     ident,
@@ -93,34 +246,383 @@ pub fn derive_validation_impl(input: TokenStream) -> syn::Result<TokenStream> {
 
         if let Some(pattern) = &field.pattern {
             if let Err(err) = Regex::new(pattern) {
-                return Err(Error::new_spanned(
-                    ident,
+                return Err(Error::new(
+                    pattern.span(),
                     format!("Pattern attr contains invalid regular expression. {err}"),
                 ));
             }
+            require(Kind::String, pattern.span(), "Pattern")?;
             let pattern = &**pattern;
-            patterns.push(quote! {
-                if !anyhow::Context::context(regex::Regex::new(#pattern), "Validation Pattern")?.is_match(&self.#ident) {
-                    return Err(crate::api::validation_error::ValidationError::Pattern { field: #ident_str, value: self.#ident.to_string() }.into());
+            codes.push("Pattern");
+            checks.push(quote! {
+                if let Some(value) = #value {
+                    static PATTERN: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+                    let pattern = PATTERN.get_or_init(|| regex::Regex::new(#pattern).expect("pattern checked by Validation derive"));
+                    if !pattern.is_match(value) {
+                        errors.push(#error::Pattern { field: #name, value: value.to_string() });
+                    }
+                }
+            });
+            keywords.push(quote! {
+                property.pattern = Some(#pattern.to_owned());
+            });
+        }
+
+        let formats = [
+            (
+                &field.email,
+                "Email",
+                quote! { is_email },
+                quote! { Email },
+                quote! { property.format = Some("email"); },
+            ),
+            (
+                &field.phone,
+                "Phone",
+                quote! { is_phone },
+                quote! { Phone },
+                quote! { property.pattern = Some(#rules::PHONE_PATTERN.to_owned()); },
+            ),
+            (
+                &field.luhn,
+                "Luhn",
+                quote! { is_luhn },
+                quote! { Luhn },
+                quote! { property.pattern = Some(#rules::LUHN_PATTERN.to_owned()); },
+            ),
+        ];
+        let formats: Vec<_> = formats
+            .into_iter()
+            .filter(|(flag, ..)| flag.is_present())
+            .collect();
+        if let Some((flag, ..)) = formats.get(1) {
+            return Err(Error::new(
+                flag.span(),
+                "Email, phone and luhn attrs are mutually exclusive",
+            ));
+        }
+        for (flag, attr, check, variant, keyword) in formats {
+            require(Kind::String, flag.span(), attr)?;
+            codes.push(attr);
+            checks.push(quote! {
+                if let Some(value) = #value {
+                    if !#rules::#check(value) {
+                        errors.push(#error::#variant { field: #name, value: value.to_string() });
+                    }
+                }
+            });
+            keywords.push(keyword);
+        }
+
+        if let Some(one_of) = &field.one_of {
+            if one_of.is_empty() {
+                return Err(Error::new(
+                    one_of.span(),
+                    "One of attr requires at least one value",
+                ));
+            }
+            require(Kind::String, one_of.span(), "One of")?;
+            codes.push("OneOf");
+            let allowed = one_of.iter();
+            checks.push(quote! {
+                if let Some(value) = #value {
+                    const ALLOWED: &[&str] = &[#(#allowed),*];
+                    if !ALLOWED.contains(&value.as_str()) {
+                        errors.push(#error::OneOf { field: #name, value: value.to_string(), allowed: ALLOWED.to_vec() });
+                    }
+                }
+            });
+            let allowed = one_of.iter();
+            keywords.push(quote! {
+                property.enum_items = vec![#(serde_json::json!(#allowed)),*];
+            });
+        }
+
+        if let Some(range) = &field.range {
+            if range.min.is_none() && range.max.is_none() {
+                return Err(Error::new(
+                    range.span(),
+                    "Range attr requires min, max or both",
+                ));
+            }
+            require(Kind::Numeric, range.span(), "Range")?;
+            codes.push("Range");
+            if let (Some(min), Some(max)) = (
+                range.min.as_ref().and_then(literal_number),
+                range.max.as_ref().and_then(literal_number),
+            ) {
+                if min > max {
+                    return Err(Error::new(
+                        range.span(),
+                        "Range attr min is greater than max",
+                    ));
+                }
+            }
+            let below = range.min.as_ref().map(|min| quote! { *value < (#min) });
+            let above = range.max.as_ref().map(|max| quote! { *value > (#max) });
+            let out_of_range = below.into_iter().chain(above);
+            let json = |bound: &Option<Expr>| match bound {
+                Some(bound) => quote! { Some(serde_json::json!(#bound)) },
+                None => quote! { None },
+            };
+            let (min, max) = (json(&range.min), json(&range.max));
+            checks.push(quote! {
+                if let Some(value) = #value {
+                    if #(#out_of_range)||* {
+                        errors.push(#error::Range { field: #name, min: #min, max: #max });
+                    }
+                }
+            });
+            if let Some(min) = &range.min {
+                keywords.push(quote! { property.minimum = Some((#min) as f64); });
+            }
+            if let Some(max) = &range.max {
+                keywords.push(quote! { property.maximum = Some((#max) as f64); });
+            }
+        }
+
+        if field.past.is_present() && field.future.is_present() {
+            return Err(Error::new(
+                field.future.span(),
+                "Past and future attrs are mutually exclusive",
+            ));
+        }
+        if field.past.is_present() {
+            require(Kind::Date, field.past.span(), "Past")?;
+            codes.push("Past");
+            checks.push(quote! {
+                if let Some(value) = #value {
+                    if *value >= #rules::today() {
+                        errors.push(#error::Past { field: #name, value: *value });
+                    }
+                }
+            });
+        }
+        if field.future.is_present() {
+            require(Kind::Date, field.future.span(), "Future")?;
+            codes.push("Future");
+            checks.push(quote! {
+                if let Some(value) = #value {
+                    if *value <= #rules::today() {
+                        errors.push(#error::Future { field: #name, value: *value });
+                    }
+                }
+            });
+        }
+        if let Some(min_age) = &field.min_age {
+            require(Kind::Date, min_age.span(), "Min age")?;
+            if field.future.is_present() {
+                return Err(Error::new(
+                    min_age.span(),
+                    "Min age attr cannot be combined with future",
+                ));
+            }
+            let min_age = **min_age;
+            codes.push("MinAge");
+            checks.push(quote! {
+                if let Some(value) = #value {
+                    if #rules::age(*value) < #min_age {
+                        errors.push(#error::MinAge { field: #name, min: #min_age });
+                    }
+                }
+            });
+        }
+
+        if !keywords.is_empty() {
+            schema.push(quote! {
+                #name => { #(#keywords)* }
+            });
+        }
+
+        if let Some(custom) = &field.custom {
+            hook_codes.extend(hook_codes_of(custom)?);
+            let custom = &custom.path;
+            checks.push(quote! {
+                if let Some(value) = #value {
+                    if let Err(err) = #custom(#name, #arg) {
+                        errors.push(err);
+                    }
+                }
+            });
+        }
+        if let Some(rule) = &field.rule {
+            hook_codes.extend(hook_codes_of(rule)?);
+            let rule = &rule.path;
+            field_rules.push(quote! {
+                if let Some(value) = #value {
+                    if let Err(err) = #rule(#arg) {
+                        errors.push(err);
+                    }
+                }
+            });
+        }
+        if let Some(check) = &field.check {
+            hook_codes.extend(hook_codes_of(check)?);
+            let check = &check.path;
+            field_checks.push(quote! {
+                if let Some(value) = #value {
+                    if let Some(err) = #check(#arg, ctx).await? {
+                        errors.push(err);
+                    }
                 }
             });
         }
     }
+    let errors_ty = quote! { crate::api::validation_error::ValidationErrors };
+    let struct_rules = input.rule.iter().map(|hook| &hook.path);
+    let validate_with = (!input.check.is_empty() || !field_checks.is_empty()).then(|| {
+        let checks = input.check.iter().map(|hook| &hook.path);
+        quote! {
+            /// Rules followed by database backed checks
+            pub async fn validate_with(
+                &mut self,
+                ctx: &crate::api::validation_rules::ValidationContext<'_>,
+            ) -> poem::Result<()> {
+                let mut errors = self.rule_errors();
+                #(#field_checks)*
+                #(
+                    if let Some(err) = #checks(self, ctx).await? {
+                        errors.push(err);
+                    }
+                )*
+                #errors_ty::check(errors)
+            }
+        }
+    });
+    let register = (!schema.is_empty()).then(|| {
+        quote! {
+            /// Writes the field rules into the schema of the payload
+            fn register(registry: &mut poem_openapi::registry::Registry) {
+                <Self as poem_openapi::types::Type>::register(registry);
+                let name = <Self as poem_openapi::types::Type>::name();
+                let Some(schema) = registry.schemas.get_mut(name.as_ref()) else {
+                    return;
+                };
+                for (field, property) in &mut schema.properties {
+                    let poem_openapi::registry::MetaSchemaRef::Inline(property) = property else {
+                        continue;
+                    };
+                    match *field {
+                        #(#schema)*
+                        _ => {}
+                    }
+                }
+            }
+        }
+    });
+    let mut codes: Vec<Path> = codes
+        .into_iter()
+        .map(|code| Ident::new(code, Span::call_site()).into())
+        .chain(hook_codes)
+        .collect();
+    codes.sort_unstable_by_key(|code| quote!(#code).to_string());
+    codes.dedup_by_key(|code| quote!(#code).to_string());
+    let field_codes = if codes.is_empty() {
+        quote! { Vec::new() }
+    } else {
+        quote! {
+            let mut codes = Vec::new();
+            #(codes.extend(<crate::api::validation_error::codes::#codes as crate::api::api_error::ErrorCodes>::codes());)*
+            codes
+        }
+    };
     Ok(quote! {
+        /// Codes the field rules, rules and checks may fail with
+        impl crate::api::api_error::ErrorCodes for #ident {
+            fn codes() -> Vec<crate::api::api_error::ErrorCode> {
+                #field_codes
+            }
+
+            #register
+        }
+
         impl #ident {
-            pub fn validate(&mut self) -> poem::Result<()> {
+            fn rule_errors(&mut self) -> Vec<#error> {
                 #(#trims)*
-                #(#lengths)*
-                #(#patterns)*
-                Ok(())
+                let mut errors = Vec::new();
+                #(#checks)*
+                #(#field_rules)*
+                #(
+                    if let Err(err) = #struct_rules(self) {
+                        errors.push(err);
+                    }
+                )*
+                errors
             }
+
+            /// Field checks followed by rules, everything not needing the database
+            pub fn validate(&mut self) -> poem::Result<()> {
+                #errors_ty::check(self.rule_errors())
+            }
+
+            #validate_with
         }
     })
 }
 
-fn get_type(name: &str) -> Type {
-    Type::Path(TypePath {
-        qself: None,
-        path: Path::from(Ident::new(name, Span::call_site())),
-    })
+/// Declared codes of a hook, at least one as every failure has a code
+fn hook_codes_of(hook: &Hook) -> syn::Result<Vec<Path>> {
+    if hook.codes.is_empty() {
+        return Err(Error::new_spanned(
+            &hook.path,
+            "Hook requires the codes it may fail with, e.g. codes(MinAge)",
+        ));
+    }
+    Ok(hook.codes.to_vec())
+}
+
+/// Expression yielding `Option<&T>` for both `T` and `Option<T>` fields
+fn value_expr(ident: &Ident, optional: bool) -> TokenStream {
+    if optional {
+        quote! { self.#ident.as_ref() }
+    } else {
+        quote! { Some(&self.#ident) }
+    }
+}
+
+/// Splits `Option<T>` into `(true, T)`, any other type into `(false, type)`
+fn unwrap_option(ty: &Type) -> (bool, &Type) {
+    if let Type::Path(path) = ty {
+        if let Some(segment) = path.path.segments.last() {
+            if segment.ident == "Option" {
+                if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                    if let Some(GenericArgument::Type(inner)) = args.args.first() {
+                        return (true, inner);
+                    }
+                }
+            }
+        }
+    }
+    (false, ty)
+}
+
+fn kind(ty: &Type) -> Kind {
+    let Type::Path(path) = ty else {
+        return Kind::Other;
+    };
+    match path.path.segments.last().map(|s| s.ident.to_string()) {
+        Some(name) if name == "String" => Kind::String,
+        Some(name) if name == "NaiveDate" => Kind::Date,
+        Some(name) if NUMERIC_TYPES.contains(&name.as_str()) => Kind::Numeric,
+        _ => Kind::Other,
+    }
+}
+
+/// Value of a possibly negated numeric literal, used to check range bounds
+fn literal_number(expr: &Expr) -> Option<f64> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(lit), ..
+        }) => lit.base10_parse().ok(),
+        Expr::Lit(ExprLit {
+            lit: Lit::Float(lit),
+            ..
+        }) => lit.base10_parse().ok(),
+        Expr::Unary(ExprUnary {
+            op: UnOp::Neg(_),
+            expr,
+            ..
+        }) => literal_number(expr).map(|value| -value),
+        _ => None,
+    }
 }
@@ -0,0 +1,4 @@
+#[test]
+fn bad_attributes() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,9 @@
+use bublik_macros::Validation;
+
+#[derive(Validation)]
+struct Payload {
+    #[val(one_of())]
+    kind: Option<String>,
+}
+
+fn main() {}
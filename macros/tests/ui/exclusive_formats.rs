@@ -0,0 +1,9 @@
+use bublik_macros::Validation;
+
+#[derive(Validation)]
+struct Payload {
+    #[val(email, phone)]
+    contact: String,
+}
+
+fn main() {}
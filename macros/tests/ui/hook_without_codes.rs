@@ -0,0 +1,9 @@
+use bublik_macros::Validation;
+
+#[derive(Validation)]
+#[val(rule(path = "adult", codes()))]
+struct Payload {
+    age: u32,
+}
+
+fn main() {}
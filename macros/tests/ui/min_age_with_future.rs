@@ -0,0 +1,11 @@
+use bublik_macros::Validation;
+
+struct NaiveDate;
+
+#[derive(Validation)]
+struct Payload {
+    #[val(future, min_age = 18)]
+    birthday: NaiveDate,
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use bublik_macros::Validation;
+
+struct NaiveDate;
+
+#[derive(Validation)]
+struct Payload {
+    #[val(past, future)]
+    birthday: Option<NaiveDate>,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use bublik_macros::Validation;
+
+#[derive(Validation)]
+struct Payload {
+    #[val(pattern = "^[0-9]+$")]
+    count: u32,
+}
+
+fn main() {}
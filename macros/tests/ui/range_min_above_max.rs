@@ -0,0 +1,9 @@
+use bublik_macros::Validation;
+
+#[derive(Validation)]
+struct Payload {
+    #[val(range(min = 10, max = -1))]
+    count: i32,
+}
+
+fn main() {}
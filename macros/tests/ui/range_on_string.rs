@@ -0,0 +1,9 @@
+use bublik_macros::Validation;
+
+#[derive(Validation)]
+struct Payload {
+    #[val(range(min = 1))]
+    name: String,
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use bublik_macros::authorize;
+
+pub struct Api;
+
+#[authorize]
+impl Api {
+    #[oai(path = "/", method = "get")]
+    async fn get(&self, auth: Auth) {}
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use bublik_macros::authorize;
+
+pub struct Api;
+
+#[authorize]
+impl Api {
+    #[oai(path = "/", method = "get")]
+    #[role(Admin)]
+    async fn get(&self, auth: Auth) {}
+}
+
+fn main() {}
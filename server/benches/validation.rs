@@ -0,0 +1,76 @@
+//! Throughput of the generated validators, run with `cargo bench -p bublik-server`
+
+use std::{hint::black_box, thread, time::Instant};
+
+use bublik_server::models::{
+    card::{CardType, CreateCard},
+    user::{CreateUser, UserType},
+};
+use chrono::NaiveDate;
+use regex::Regex;
+
+const ITERATIONS: u32 = 20_000;
+const EXPIRATION: &str = r"^(0[1-9]|1[0-2])\/?([0-9]{4}|[0-9]{2})$";
+
+fn main() {
+    let cores = thread::available_parallelism().map_or(4, usize::from);
+    let mut threads = vec![1, 4, cores];
+    threads.sort_unstable();
+    threads.dedup();
+    println!("{:<28} {:>7} {:>12}", "benchmark", "threads", "ns/op");
+    for threads in threads {
+        bench("CreateUser::validate", threads, || {
+            create_user().validate().unwrap();
+        });
+        bench("CreateCard::validate", threads, || {
+            create_card().validate().unwrap();
+        });
+        // What every `validate()` call used to pay before patterns were cached
+        bench("CreateCard regex recompiled", threads, || {
+            assert!(recompiled(&create_card().expiration));
+        });
+    }
+}
+
+/// Runs `op` `ITERATIONS` times on each of `threads` threads at once
+fn bench(name: &str, threads: usize, op: impl Fn() + Sync) {
+    let start = Instant::now();
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                for _ in 0..ITERATIONS {
+                    black_box(&op)();
+                }
+            });
+        }
+    });
+    let per_op = start.elapsed() / (ITERATIONS * threads as u32);
+    println!("{name:<28} {threads:>7} {:>12}", per_op.as_nanos());
+}
+
+fn recompiled(value: &str) -> bool {
+    Regex::new(EXPIRATION).unwrap().is_match(value)
+}
+
+fn create_user() -> CreateUser {
+    CreateUser {
+        first_name: " Blaze ".to_owned(),
+        last_name: "Lesch".to_owned(),
+        email: "gschaefer@spencer.com".to_owned(),
+        phone: "+3717908711215".to_owned(),
+        birthday: NaiveDate::from_ymd_opt(1964, 4, 4).unwrap(),
+        user_type: UserType::Worker,
+        bank_id: None,
+    }
+}
+
+fn create_card() -> CreateCard {
+    CreateCard {
+        card_type: CardType::Visa,
+        number: "4916136072578625".to_owned(),
+        expiration: "07/34".to_owned(),
+        owner: "Wava Nikolaus".to_owned(),
+        owner_id: None,
+        bank_id: None,
+    }
+}
@@ -0,0 +1,8 @@
+-- Baseline schema, previously created inline on startup
+CREATE TABLE IF NOT EXISTS users (id INTEGER PRIMARY KEY NOT NULL, firstName TEXT NOT NULL, lastName TEXT NOT NULL, email TEXT NOT NULL UNIQUE, phone TEXT NOT NULL, birthday TEXT NOT NULL, userType INTEGER NOT NULL);
+CREATE TABLE IF NOT EXISTS cards (id INTEGER PRIMARY KEY NOT NULL, cardType INTEGER NOT NULL, number TEXT NOT NULL UNIQUE, expiration TEXT NOT NULL, owner TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS banks (id INTEGER PRIMARY KEY NOT NULL, country TEXT NOT NULL, city TEXT NOT NULL, zipcode TEXT NOT NULL, street TEXT NOT NULL, buildingNumber TEXT NOT NULL);
+
+-- Migration history kept by sqlx
+CREATE VIEW IF NOT EXISTS schema_migrations AS
+    SELECT version, description, installed_on, success, execution_time FROM _sqlx_migrations;
@@ -0,0 +1,8 @@
+-- Card owner and issuing bank, user home bank
+ALTER TABLE cards ADD COLUMN ownerId INTEGER REFERENCES users(id);
+ALTER TABLE cards ADD COLUMN bankId INTEGER REFERENCES banks(id);
+ALTER TABLE users ADD COLUMN bankId INTEGER REFERENCES banks(id);
+
+CREATE INDEX cards_owner_id ON cards(ownerId);
+CREATE INDEX cards_bank_id ON cards(bankId);
+CREATE INDEX users_bank_id ON users(bankId);
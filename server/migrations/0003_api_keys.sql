@@ -0,0 +1,10 @@
+-- API keys, only the SHA-256 hash of each key is stored
+CREATE TABLE api_keys (
+    id INTEGER PRIMARY KEY NOT NULL,
+    userId INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    hash TEXT NOT NULL UNIQUE,
+    createdOn TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX api_keys_user_id ON api_keys(userId);
@@ -0,0 +1,83 @@
+use std::time::Instant;
+
+use async_trait::async_trait;
+use hyper::body::HttpBody;
+use poem::{http::header, Endpoint, IntoResponse, Middleware, Request, Response};
+use tracing::info;
+
+use super::{request_id::RequestId, route_templates::RouteTemplates};
+
+/// Logs one `access` event per request with its method, route template, status, latency,
+/// response size and request id
+pub struct AccessLog {
+    templates: RouteTemplates,
+}
+
+impl AccessLog {
+    pub fn new(templates: RouteTemplates) -> Self {
+        Self { templates }
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for AccessLog {
+    type Output = AccessLogEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        AccessLogEndpoint {
+            inner: ep,
+            templates: self.templates.clone(),
+        }
+    }
+}
+
+pub struct AccessLogEndpoint<E> {
+    inner: E,
+    templates: RouteTemplates,
+}
+
+#[async_trait]
+impl<E: Endpoint> Endpoint for AccessLogEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let start = Instant::now();
+        let method = req.method().clone();
+        let route = self
+            .templates
+            .matching(req.uri().path())
+            .unwrap_or("unmatched")
+            .to_owned();
+        let request_id = req.extensions().get::<RequestId>().map(|id| id.0.clone());
+        let mut res = match self.inner.call(req).await {
+            Ok(res) => res.into_response(),
+            Err(err) => err.into_response(),
+        };
+        let size = response_size(&mut res);
+        info!(
+            target: "access",
+            method = %method,
+            route,
+            status = res.status().as_u16(),
+            latency_ms = start.elapsed().as_secs_f64() * 1000.0,
+            size,
+            request_id = request_id.as_deref().unwrap_or_default(),
+            "access"
+        );
+        Ok(res)
+    }
+}
+
+/// Size announced by `Content-Length` or known from the body without reading it, streamed
+/// bodies of unknown length are logged without a size
+fn response_size(res: &mut Response) -> Option<u64> {
+    let length = res
+        .header(header::CONTENT_LENGTH)
+        .and_then(|length| length.parse().ok());
+    if length.is_some() {
+        return length;
+    }
+    let body = hyper::Body::from(res.take_body());
+    let size = HttpBody::size_hint(&body).exact();
+    res.set_body(body);
+    size
+}
@@ -0,0 +1,184 @@
+use poem::{http::StatusCode, IntoResponse, Response};
+use poem_openapi::{
+    registry::{
+        MetaDiscriminatorObject, MetaMediaType, MetaResponse, MetaResponses, MetaSchema,
+        MetaSchemaRef, Registry,
+    },
+    types::Type,
+    ApiResponse,
+};
+use std::{collections::BTreeMap, marker::PhantomData};
+
+use super::{
+    problem::{Problem, PROBLEM_CONTENT_TYPE},
+    validation_error::{codes, ValidationError, ValidationErrors},
+};
+
+/// Documented error code with its response status and `{Variant}Error` schema
+pub struct ErrorCode {
+    pub code: &'static str,
+    pub status: u16,
+    pub schema: &'static str,
+}
+
+/// Error codes an operation may fail with, tuples of sets are sets as well
+pub trait ErrorCodes {
+    fn codes() -> Vec<ErrorCode>;
+
+    /// Documents the rules behind the codes, validated payloads add them to their schema
+    fn register(_registry: &mut Registry) {}
+}
+
+macro_rules! impl_error_codes {
+    ($($codes:ident),+) => {
+        impl<$($codes: ErrorCodes),+> ErrorCodes for ($($codes,)+) {
+            fn codes() -> Vec<ErrorCode> {
+                let mut codes = Vec::new();
+                $(codes.extend($codes::codes());)+
+                codes
+            }
+
+            fn register(registry: &mut Registry) {
+                $($codes::register(registry);)+
+            }
+        }
+    };
+}
+
+impl_error_codes!(A);
+impl_error_codes!(A, B);
+impl_error_codes!(A, B, C);
+impl_error_codes!(A, B, C, D);
+impl_error_codes!(A, B, C, D, E);
+impl_error_codes!(A, B, C, D, E, F);
+impl_error_codes!(A, B, C, D, E, F, G);
+impl_error_codes!(A, B, C, D, E, F, G, H);
+
+/// Error of an API operation, documented with one response per status of the codes in `C`
+pub struct ApiError<C = ValidationError>(poem::Error, PhantomData<fn() -> C>);
+
+pub type ApiResult<T, C = ValidationError> = Result<T, ApiError<C>>;
+
+/// One response per status, its body the union of the codes sharing that status, including
+/// the internal error every operation may end with
+pub fn responses(codes: Vec<ErrorCode>) -> MetaResponses {
+    let mut statuses = BTreeMap::<u16, Vec<ErrorCode>>::new();
+    for code in codes.into_iter().chain(codes::Internal::codes()) {
+        let same = statuses.entry(code.status).or_default();
+        if same.iter().all(|other| other.code != code.code) {
+            same.push(code);
+        }
+    }
+    let responses = statuses
+        .into_iter()
+        .map(|(status, codes)| MetaResponse {
+            description: StatusCode::from_u16(status)
+                .ok()
+                .and_then(|status| status.canonical_reason())
+                .unwrap_or("Request was rejected, see `code`"),
+            status: Some(status),
+            content: vec![
+                MetaMediaType {
+                    content_type: "application/json; charset=utf-8",
+                    schema: schema(&codes),
+                },
+                problem_media_type(),
+            ],
+            headers: vec![],
+        })
+        .collect();
+    MetaResponses { responses }
+}
+
+/// Problem details, sent to clients preferring them in `Accept`
+fn problem_media_type() -> MetaMediaType {
+    MetaMediaType {
+        content_type: PROBLEM_CONTENT_TYPE,
+        schema: Problem::schema_ref(),
+    }
+}
+
+fn schema(codes: &[ErrorCode]) -> MetaSchemaRef {
+    if let [code] = codes {
+        return MetaSchemaRef::Reference(code.schema.to_owned());
+    }
+    MetaSchemaRef::Inline(Box::new(MetaSchema {
+        one_of: codes
+            .iter()
+            .map(|code| MetaSchemaRef::Reference(code.schema.to_owned()))
+            .collect(),
+        discriminator: Some(MetaDiscriminatorObject {
+            property_name: "code",
+            mapping: codes
+                .iter()
+                .map(|code| {
+                    let target = format!("#/components/schemas/{}", code.schema);
+                    (code.code.to_owned(), target)
+                })
+                .collect(),
+        }),
+        ..MetaSchema::new("")
+    }))
+}
+
+impl<C: ErrorCodes> ApiResponse for ApiError<C> {
+    fn meta() -> MetaResponses {
+        responses(C::codes())
+    }
+
+    fn register(registry: &mut Registry) {
+        <ValidationError as ApiResponse>::register(registry);
+        Problem::register(registry);
+        C::register(registry);
+    }
+}
+
+impl<C> IntoResponse for ApiError<C> {
+    fn into_response(self) -> Response {
+        self.0.into_response()
+    }
+}
+
+impl<C> From<ApiError<C>> for poem::Error {
+    fn from(err: ApiError<C>) -> Self {
+        err.0
+    }
+}
+
+impl<C: ErrorCodes> From<poem::Error> for ApiError<C> {
+    fn from(err: poem::Error) -> Self {
+        if cfg!(debug_assertions) {
+            if let Some(errors) = err.downcast_ref::<ValidationErrors>() {
+                errors.0.iter().for_each(assert_declared::<C>);
+            } else if let Some(error) = err.downcast_ref::<ValidationError>() {
+                assert_declared::<C>(error);
+            }
+        }
+        Self(err, PhantomData)
+    }
+}
+
+impl<C: ErrorCodes> From<ValidationError> for ApiError<C> {
+    fn from(err: ValidationError) -> Self {
+        if cfg!(debug_assertions) {
+            assert_declared::<C>(&err);
+        }
+        Self(err.into(), PhantomData)
+    }
+}
+
+/// Fails debug builds on errors missing from the documented codes of their operation
+fn assert_declared<C: ErrorCodes>(err: &ValidationError) {
+    let code = err.to_string();
+    assert!(
+        matches!(err, ValidationError::Internal)
+            || C::codes().iter().any(|declared| declared.code == code),
+        "{code} is not declared by the operation"
+    );
+}
+
+impl<C> From<anyhow::Error> for ApiError<C> {
+    fn from(err: anyhow::Error) -> Self {
+        Self(err.into(), PhantomData)
+    }
+}
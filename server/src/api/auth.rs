@@ -0,0 +1,437 @@
+use anyhow::{anyhow, bail, Context};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use int_enum::IntEnum;
+use jsonwebtoken::{
+    decode, encode, get_current_timestamp, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use poem::{http::header, Endpoint, Middleware, Request};
+use poem_openapi::{
+    auth::{ApiKey, Bearer},
+    SecurityScheme,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{query, query_as, Pool, Sqlite};
+
+use super::validation_error::ValidationError;
+use crate::{config::AuthCommand, models::user::UserType};
+
+const API_KEY_HEADER: &str = "X-API-Key";
+
+/// Authenticated caller, inserted into request extensions by [`Authentication`]
+#[derive(Clone, Copy)]
+pub struct Identity {
+    pub user_id: u32,
+    pub user_type: UserType,
+}
+
+/// Credentials accepted by the API, either scheme may be used
+#[derive(SecurityScheme)]
+pub enum Auth {
+    ApiKey(ApiKeyAuth),
+    Bearer(BearerAuth),
+}
+
+/// Key issued with `bublik-server auth key`
+#[derive(SecurityScheme)]
+#[oai(
+    ty = "api_key",
+    key_name = "X-API-Key",
+    key_in = "header",
+    checker = "api_key_identity"
+)]
+pub struct ApiKeyAuth(Identity);
+
+/// Token issued with `bublik-server auth token`
+#[derive(SecurityScheme)]
+#[oai(ty = "bearer", bearer_format = "JWT", checker = "bearer_identity")]
+pub struct BearerAuth(Identity);
+
+impl Auth {
+    pub fn identity(&self) -> Identity {
+        match self {
+            Self::ApiKey(auth) => auth.0,
+            Self::Bearer(auth) => auth.0,
+        }
+    }
+
+    /// Fails with `Forbidden` unless the caller has `role` or a higher one
+    pub fn require(&self, role: UserType) -> poem::Result<Identity> {
+        let identity = self.identity();
+        if rank(identity.user_type) >= rank(role) {
+            Ok(identity)
+        } else {
+            Err(ValidationError::Forbidden(role).into())
+        }
+    }
+
+    /// Like [`Auth::require`], additionally letting `owner` through with any role
+    pub fn require_owner(
+        &self,
+        role: UserType,
+        owner: impl Into<Option<u32>>,
+    ) -> poem::Result<Identity> {
+        let identity = self.identity();
+        if owner.into() == Some(identity.user_id) {
+            Ok(identity)
+        } else {
+            self.require(role)
+        }
+    }
+}
+
+/// Roles ordered by privilege, each includes the permissions of the lower ones
+fn rank(user_type: UserType) -> u8 {
+    match user_type {
+        UserType::Customer => 0,
+        UserType::Worker => 1,
+        UserType::Manager => 2,
+    }
+}
+
+async fn api_key_identity(req: &Request, _: ApiKey) -> Option<Identity> {
+    req.extensions().get().copied()
+}
+
+async fn bearer_identity(req: &Request, _: Bearer) -> Option<Identity> {
+    req.extensions().get().copied()
+}
+
+#[derive(Serialize, Deserialize)]
+struct Claims {
+    sub: u32,
+    exp: u64,
+}
+
+/// Rejects requests without a valid API key or bearer token, except under public paths
+pub struct Authentication {
+    db: Pool<Sqlite>,
+    jwt_secret: Option<String>,
+    public: Vec<&'static str>,
+}
+
+impl Authentication {
+    pub fn new(db: &Pool<Sqlite>, jwt_secret: Option<&str>) -> Self {
+        Self {
+            db: db.clone(),
+            jwt_secret: jwt_secret.map(str::to_owned),
+            public: Vec::new(),
+        }
+    }
+
+    /// Lets requests to `prefix` and the paths below it through without credentials
+    pub fn public(mut self, prefix: &'static str) -> Self {
+        self.public.push(prefix);
+        self
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for Authentication {
+    type Output = AuthenticationEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        AuthenticationEndpoint {
+            inner: ep,
+            db: self.db.clone(),
+            decoding_key: self
+                .jwt_secret
+                .as_ref()
+                .map(|secret| DecodingKey::from_secret(secret.as_bytes())),
+            public: self.public.clone(),
+        }
+    }
+}
+
+pub struct AuthenticationEndpoint<E> {
+    inner: E,
+    db: Pool<Sqlite>,
+    decoding_key: Option<DecodingKey>,
+    public: Vec<&'static str>,
+}
+
+#[async_trait]
+impl<E: Endpoint> Endpoint for AuthenticationEndpoint<E> {
+    type Output = E::Output;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        let path = req.uri().path();
+        let public = self.public.iter().any(|prefix| {
+            path.strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        });
+        if public {
+            return self.inner.call(req).await;
+        }
+        let identity = self.authenticate(&req).await?;
+        req.extensions_mut().insert(identity);
+        self.inner.call(req).await
+    }
+}
+
+impl<E> AuthenticationEndpoint<E> {
+    async fn authenticate(&self, req: &Request) -> poem::Result<Identity> {
+        let user_id = if let Some(key) = req.header(API_KEY_HEADER) {
+            query_as::<_, (u32,)>("SELECT userId FROM api_keys WHERE hash = ?")
+                .bind(hash_key(key))
+                .fetch_optional(&self.db)
+                .await
+                .context("find api key")?
+                .map(|(user_id,)| user_id)
+        } else if let Some(token) = req
+            .header(header::AUTHORIZATION)
+            .and_then(|value| value.strip_prefix("Bearer "))
+        {
+            self.decoding_key.as_ref().and_then(|key| {
+                decode::<Claims>(token, key, &Validation::new(Algorithm::HS256))
+                    .ok()
+                    .map(|token| token.claims.sub)
+            })
+        } else {
+            None
+        };
+        let Some(user_id) = user_id else {
+            return Err(ValidationError::Unauthorized.into());
+        };
+        let Some((user_type,)) = query_as::<_, (u32,)>("SELECT userType FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await
+            .context("find identity")?
+        else {
+            return Err(ValidationError::Unauthorized.into());
+        };
+        // Unknown types must not fall back to any role
+        let user_type = UserType::from_int(user_type)
+            .map_err(|_| anyhow!("user {user_id} has unknown type {user_type}"))?;
+        Ok(Identity { user_id, user_type })
+    }
+}
+
+/// Issues API keys and tokens from the command line
+pub async fn command(
+    db: &Pool<Sqlite>,
+    jwt_secret: Option<&str>,
+    command: AuthCommand,
+) -> anyhow::Result<()> {
+    match command {
+        AuthCommand::Key { user, name } => {
+            ensure_user(db, user).await?;
+            let mut bytes = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let key = format!("bublik_{}", URL_SAFE_NO_PAD.encode(bytes));
+            query("INSERT INTO api_keys (userId, name, hash) VALUES (?, ?, ?)")
+                .bind(user)
+                .bind(name)
+                .bind(hash_key(&key))
+                .execute(db)
+                .await
+                .context("insert api key")?;
+            println!("{key}");
+        }
+        AuthCommand::Token { user, ttl } => {
+            let Some(secret) = jwt_secret else {
+                bail!("jwt secret is not configured");
+            };
+            ensure_user(db, user).await?;
+            let claims = Claims {
+                sub: user,
+                exp: get_current_timestamp() + ttl,
+            };
+            let token = encode(
+                &Header::new(Algorithm::HS256),
+                &claims,
+                &EncodingKey::from_secret(secret.as_bytes()),
+            )
+            .context("sign token")?;
+            println!("{token}");
+        }
+    }
+    Ok(())
+}
+
+async fn ensure_user(db: &Pool<Sqlite>, user: u32) -> anyhow::Result<()> {
+    if query("SELECT 1 FROM users WHERE id = ?")
+        .bind(user)
+        .fetch_optional(db)
+        .await
+        .context("find user")?
+        .is_none()
+    {
+        bail!("user {user} does not exist");
+    }
+    Ok(())
+}
+
+fn hash_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::endpoint::make_sync;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+    use crate::db::migrate;
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+    const KEY: &str = "bublik_key";
+
+    /// In-memory database holding worker 1 with [`KEY`] and user 2 of an unknown type
+    async fn database() -> Pool<Sqlite> {
+        // A single connection keeps the in-memory database alive
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        migrate::run(&db).await.unwrap();
+        query(
+            "INSERT INTO users (id, firstName, lastName, email, phone, birthday, userType) VALUES
+                (1, 'Adam', 'Nowak', 'adam@bublik.pl', '+48100200300', '1990-01-01', 1),
+                (2, 'Ola', 'Nowak', 'ola@bublik.pl', '+48100200301', '1990-01-01', 9)",
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        query("INSERT INTO api_keys (userId, name, hash) VALUES (1, 'test', ?)")
+            .bind(hash_key(KEY))
+            .execute(&db)
+            .await
+            .unwrap();
+        db
+    }
+
+    async fn authentication(
+        jwt_secret: Option<&str>,
+    ) -> AuthenticationEndpoint<impl Endpoint<Output = &'static str>> {
+        Authentication::new(&database().await, jwt_secret)
+            .public("/health")
+            .transform(make_sync(|_| "ok"))
+    }
+
+    fn token(sub: u32, exp: u64, secret: &str) -> String {
+        encode(
+            &Header::new(Algorithm::HS256),
+            &Claims { sub, exp },
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    fn bearer(token: &str) -> Request {
+        Request::builder()
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .finish()
+    }
+
+    fn is_unauthorized(result: poem::Result<Identity>) -> bool {
+        matches!(
+            result.map_err(|err| err.downcast::<ValidationError>()),
+            Err(Ok(ValidationError::Unauthorized))
+        )
+    }
+
+    #[tokio::test]
+    async fn authenticates_api_keys() {
+        let endpoint = authentication(None).await;
+        let req = Request::builder().header(API_KEY_HEADER, KEY).finish();
+        let identity = endpoint.authenticate(&req).await.unwrap();
+        assert_eq!(identity.user_id, 1);
+        assert!(matches!(identity.user_type, UserType::Worker));
+
+        let req = Request::builder()
+            .header(API_KEY_HEADER, "unknown")
+            .finish();
+        assert!(is_unauthorized(endpoint.authenticate(&req).await));
+        assert!(is_unauthorized(
+            endpoint.authenticate(&Request::default()).await
+        ));
+    }
+
+    #[tokio::test]
+    async fn authenticates_tokens() {
+        let endpoint = authentication(Some(SECRET)).await;
+        let hour_later = get_current_timestamp() + 3600;
+        let valid = bearer(&token(1, hour_later, SECRET));
+        assert_eq!(endpoint.authenticate(&valid).await.unwrap().user_id, 1);
+
+        let hour_ago = get_current_timestamp() - 3600;
+        for req in [
+            bearer(&token(1, hour_ago, SECRET)),
+            bearer(&token(1, hour_later, "another secret")),
+            bearer("not a token"),
+            bearer(&token(3, hour_later, SECRET)),
+        ] {
+            assert!(is_unauthorized(endpoint.authenticate(&req).await));
+        }
+
+        let without_secret = authentication(None).await;
+        assert!(is_unauthorized(without_secret.authenticate(&valid).await));
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_user_types() {
+        let endpoint = authentication(Some(SECRET)).await;
+        let req = bearer(&token(2, get_current_timestamp() + 3600, SECRET));
+        let Err(err) = endpoint.authenticate(&req).await else {
+            panic!("user of an unknown type authenticated");
+        };
+        assert!(err.downcast_ref::<ValidationError>().is_none());
+    }
+
+    #[tokio::test]
+    async fn lets_public_paths_through() {
+        let endpoint = authentication(None).await;
+        let req = Request::builder().uri_str("/health/ready").finish();
+        assert_eq!(endpoint.call(req).await.unwrap(), "ok");
+        for path in ["/healthy", "/user/1"] {
+            let req = Request::builder().uri_str(path).finish();
+            assert!(endpoint.call(req).await.is_err());
+        }
+    }
+
+    fn auth(user_id: u32, user_type: UserType) -> Auth {
+        Auth::ApiKey(ApiKeyAuth(Identity { user_id, user_type }))
+    }
+
+    fn is_forbidden(result: poem::Result<Identity>, role: UserType) -> bool {
+        matches!(
+            result.map_err(|err| err.downcast::<ValidationError>()),
+            Err(Ok(ValidationError::Forbidden(required))) if rank(required) == rank(role)
+        )
+    }
+
+    #[test]
+    fn roles_include_lower_ones() {
+        assert!(rank(UserType::Customer) < rank(UserType::Worker));
+        assert!(rank(UserType::Worker) < rank(UserType::Manager));
+
+        let worker = auth(1, UserType::Worker);
+        assert!(worker.require(UserType::Customer).is_ok());
+        assert!(worker.require(UserType::Worker).is_ok());
+        assert!(is_forbidden(
+            worker.require(UserType::Manager),
+            UserType::Manager
+        ));
+        assert!(auth(2, UserType::Manager).require(UserType::Worker).is_ok());
+    }
+
+    #[test]
+    fn owners_pass_without_the_role() {
+        let customer = auth(3, UserType::Customer);
+        assert!(customer.require_owner(UserType::Worker, 3).is_ok());
+        assert!(is_forbidden(
+            customer.require_owner(UserType::Worker, 4),
+            UserType::Worker
+        ));
+        assert!(is_forbidden(
+            customer.require_owner(UserType::Worker, None),
+            UserType::Worker
+        ));
+        let worker = auth(1, UserType::Worker);
+        assert!(worker.require_owner(UserType::Worker, 3).is_ok());
+    }
+}
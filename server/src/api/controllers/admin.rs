@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+
+use poem_openapi::{payload::Json, OpenApi};
+use sqlx::{Pool, Sqlite};
+
+use super::prelude::*;
+use crate::{
+    config::Config,
+    db::{seed, SeedStatus},
+    models::seed::{SeedReport, SeedRequest},
+};
+
+pub struct Api {
+    db: Pool<Sqlite>,
+    data_dir: PathBuf,
+    status: SeedStatus,
+}
+
+pub fn api(db: &Pool<Sqlite>, config: &Config, status: &SeedStatus) -> Api {
+    Api {
+        db: db.clone(),
+        data_dir: config.data_dir.clone(),
+        status: status.clone(),
+    }
+}
+
+#[authorize]
+#[OpenApi(prefix_path = "/admin", tag = "super::Tags::Admin")]
+impl Api {
+    /// Seed Database
+    #[oai(path = "/seed", method = "post")]
+    #[role(Manager)]
+    async fn seed(
+        &self,
+        auth: Auth,
+        mut data: Json<SeedRequest>,
+    ) -> Result<Json<SeedReport>, (Denied, SeedRequest, codes::ReferenceNotExists)> {
+        data.validate()?;
+        let dir = match &data.dataset {
+            Some(dataset) => self.data_dir.join(dataset),
+            None => self.data_dir.clone(),
+        };
+        if !dir.is_dir() {
+            return Err(ReferenceNotExists("Dataset").into());
+        }
+        let _running = self.status.running();
+        Ok(Json(seed::seed(&self.db, &dir, data.mode).await?))
+    }
+}
@@ -2,39 +2,91 @@ use std::ops::Deref;
 
 use anyhow::Context;
 use poem_openapi::{param::Path, payload::Json, OpenApi};
-use sqlx::{query, query_as, Pool, Sqlite};
+use sqlx::{query, query_as, Pool, QueryBuilder, Sqlite};
 
-use super::prelude::*;
-use crate::models::{
-    bank::{Bank, CreateBank},
-    browse::Browse,
+use super::{prelude::*, relations};
+use crate::{
+    config::{Config, Relations},
+    models::{
+        bank::{Bank, BankFilter, CreateBank, PatchBank},
+        browse::{Browse, Page},
+        card::Card,
+        user::User,
+    },
 };
 
 pub struct Api {
     db: Pool<Sqlite>,
+    relations: Relations,
 }
 
-pub fn api(db: &Pool<Sqlite>) -> Api {
-    Api { db: db.clone() }
+pub fn api(db: &Pool<Sqlite>, config: &Config) -> Api {
+    Api {
+        db: db.clone(),
+        relations: config.relations,
+    }
 }
 
+#[authorize]
 #[OpenApi(prefix_path = "/bank", tag = "super::Tags::Bank")]
 impl Api {
     /// Get Bank
     #[oai(path = "/:id", method = "get")]
-    async fn get(&self, id: Path<u32>) -> Result<Json<Bank>> {
+    #[role(Customer)]
+    async fn get(
+        &self,
+        auth: Auth,
+        id: Path<u32>,
+    ) -> Result<Json<Bank>, (codes::Unauthorized, codes::EntityNotExists)> {
         query_as::<_, Bank>("SELECT * FROM banks WHERE id = ?")
             .bind(*id)
             .fetch_optional(&self.db)
             .await
             .context("get bank")?
-            .map(|o| Json(o))
+            .map(Json)
             .ok_or(EntityNotExists("Bank").into())
     }
 
+    /// Get Bank Cards
+    #[oai(path = "/:id/cards", method = "get")]
+    #[role(Worker)]
+    async fn cards(
+        &self,
+        auth: Auth,
+        id: Path<u32>,
+    ) -> Result<Json<Vec<Card>>, (Denied, codes::EntityNotExists)> {
+        ensure_exists(&self.db, "banks", "Bank", *id).await?;
+        Ok(Json(
+            query_as::<_, Card>("SELECT * FROM cards WHERE bankId = ? ORDER BY id")
+                .bind(*id)
+                .fetch_all(&self.db)
+                .await
+                .context("get bank cards")?,
+        ))
+    }
+
+    /// Get Bank Users
+    #[oai(path = "/:id/users", method = "get")]
+    #[role(Worker)]
+    async fn users(
+        &self,
+        auth: Auth,
+        id: Path<u32>,
+    ) -> Result<Json<Vec<User>>, (Denied, codes::EntityNotExists)> {
+        ensure_exists(&self.db, "banks", "Bank", *id).await?;
+        Ok(Json(
+            query_as::<_, User>("SELECT * FROM users WHERE bankId = ? ORDER BY id")
+                .bind(*id)
+                .fetch_all(&self.db)
+                .await
+                .context("get bank users")?,
+        ))
+    }
+
     /// Count Banks
     #[oai(path = "/count", method = "get")]
-    async fn count(&self) -> Result<Json<u32>> {
+    #[role(Customer)]
+    async fn count(&self, auth: Auth) -> Result<Json<u32>, codes::Unauthorized> {
         Ok(Json(
             query_as::<_, (u32,)>("SELECT COUNT(*) FROM banks")
                 .fetch_one(&self.db)
@@ -46,22 +98,25 @@ impl Api {
 
     /// Browse Banks
     #[oai(path = "/browse", method = "post")]
-    async fn browse(&self, data: Json<Browse>) -> Result<Json<Vec<Bank>>> {
+    #[role(Customer)]
+    async fn browse(
+        &self,
+        auth: Auth,
+        data: Json<Browse<BankFilter>>,
+    ) -> Result<Json<Page<Bank>>, (codes::Unauthorized, Browse<BankFilter>)> {
         let data = data.deref();
-        let skip = data.page_number() * data.count();
-        Ok(Json(
-            query_as::<_, Bank>("SELECT * FROM banks LIMIT ?, ?")
-                .bind(skip)
-                .bind(data.count())
-                .fetch_all(&self.db)
-                .await
-                .context("browse banks")?,
-        ))
+        data.validate()?;
+        Ok(Json(data.fetch(&self.db, "banks").await?))
     }
 
     /// Create Bank
     #[oai(path = "/", method = "post")]
-    async fn create(&self, mut data: Json<CreateBank>) -> Result<Json<u32>> {
+    #[role(Manager)]
+    async fn create(
+        &self,
+        auth: Auth,
+        mut data: Json<CreateBank>,
+    ) -> Result<Json<u32>, (Denied, CreateBank)> {
         data.validate()?;
         let result =
             query("INSERT INTO banks (country, city, zipcode, street, buildingNumber) VALUES (?, ?, ?, ?, ?)")
@@ -76,18 +131,87 @@ impl Api {
         Ok(Json(result.last_insert_rowid() as u32))
     }
 
-    /// Delete Bank
-    #[oai(path = "/:id", method = "delete")]
-    async fn delete(&self, id: Path<u32>) -> Result<()> {
-        let result = query("DELETE FROM banks WHERE id = ?")
+    /// Update Bank
+    #[oai(path = "/:id", method = "put")]
+    #[role(Manager)]
+    async fn update(
+        &self,
+        auth: Auth,
+        id: Path<u32>,
+        mut data: Json<CreateBank>,
+    ) -> Result<(), (Denied, CreateBank, codes::EntityNotExists)> {
+        data.validate()?;
+        let result = query("UPDATE banks SET country = ?, city = ?, zipcode = ?, street = ?, buildingNumber = ? WHERE id = ?")
+            .bind(&data.country)
+            .bind(&data.city)
+            .bind(&data.zipcode)
+            .bind(&data.street)
+            .bind(&data.building_number)
             .bind(*id)
             .execute(&self.db)
             .await
-            .context("delete bank")?;
+            .context("update bank")?;
+        if result.rows_affected() == 0 {
+            Err(EntityNotExists("Bank").into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Patch Bank
+    #[oai(path = "/:id", method = "patch")]
+    #[role(Manager)]
+    async fn patch(
+        &self,
+        auth: Auth,
+        id: Path<u32>,
+        mut data: Json<PatchBank>,
+    ) -> Result<(), (Denied, PatchBank, codes::EntityNotExists)> {
+        data.validate()?;
+        let mut query = QueryBuilder::<Sqlite>::new("UPDATE banks SET id = id");
+        if let Some(country) = &data.country {
+            query.push(", country = ").push_bind(country);
+        }
+        if let Some(city) = &data.city {
+            query.push(", city = ").push_bind(city);
+        }
+        if let Some(zipcode) = &data.zipcode {
+            query.push(", zipcode = ").push_bind(zipcode);
+        }
+        if let Some(street) = &data.street {
+            query.push(", street = ").push_bind(street);
+        }
+        if let Some(building_number) = &data.building_number {
+            query.push(", buildingNumber = ").push_bind(building_number);
+        }
+        query.push(" WHERE id = ").push_bind(*id);
+        let result = query
+            .build()
+            .execute(&self.db)
+            .await
+            .context("patch bank")?;
         if result.rows_affected() == 0 {
             Err(EntityNotExists("Bank").into())
         } else {
             Ok(())
         }
     }
+
+    /// Delete Bank
+    #[oai(path = "/:id", method = "delete")]
+    #[role(Manager)]
+    async fn delete(
+        &self,
+        auth: Auth,
+        id: Path<u32>,
+    ) -> Result<(), (Denied, codes::EntityNotExists, codes::EntityHasDependents)> {
+        let mut tx = self.db.begin().await.context("begin delete bank")?;
+        let deleted = relations::delete_bank(&mut tx, *id, &self.relations).await?;
+        tx.commit().await.context("commit delete bank")?;
+        if deleted == 0 {
+            Err(EntityNotExists("Bank").into())
+        } else {
+            Ok(())
+        }
+    }
 }
@@ -2,12 +2,12 @@ use std::ops::Deref;
 
 use anyhow::Context;
 use poem_openapi::{param::Path, payload::Json, OpenApi};
-use sqlx::{error::ErrorKind, query, query_as, Pool, Sqlite};
+use sqlx::{error::ErrorKind, query, query_as, Pool, QueryBuilder, Sqlite};
 
 use super::prelude::*;
 use crate::models::{
-    browse::Browse,
-    card::{Card, CreateCard},
+    browse::{Browse, Page},
+    card::{Card, CardFilter, CreateCard, PatchCard},
 };
 
 pub struct Api {
@@ -18,23 +18,45 @@ pub fn api(db: &Pool<Sqlite>) -> Api {
     Api { db: db.clone() }
 }
 
+impl Api {
+    /// Owner of a card, `None` for missing cards as well, so they cannot be told apart from
+    /// cards of other users
+    async fn card_owner(&self, id: u32) -> poem::Result<Option<u32>> {
+        Ok(
+            query_as::<_, (Option<u32>,)>("SELECT ownerId FROM cards WHERE id = ?")
+                .bind(id)
+                .fetch_optional(&self.db)
+                .await
+                .context("get card owner")?
+                .and_then(|(owner_id,)| owner_id),
+        )
+    }
+}
+
+#[authorize]
 #[OpenApi(prefix_path = "/card", tag = "super::Tags::Card")]
 impl Api {
     /// Get Card
     #[oai(path = "/:id", method = "get")]
-    async fn get(&self, id: Path<u32>) -> Result<Json<Card>> {
+    #[role(Worker, owner = "self.card_owner(*id).await?")]
+    async fn get(
+        &self,
+        auth: Auth,
+        id: Path<u32>,
+    ) -> Result<Json<Card>, (Denied, codes::EntityNotExists)> {
         query_as::<_, Card>("SELECT * FROM cards WHERE id = ?")
             .bind(*id)
             .fetch_optional(&self.db)
             .await
             .context("get card")?
-            .map(|o| Json(o))
+            .map(Json)
             .ok_or(EntityNotExists("Card").into())
     }
 
     /// Count Cards
     #[oai(path = "/count", method = "get")]
-    async fn count(&self) -> Result<Json<u32>> {
+    #[role(Worker)]
+    async fn count(&self, auth: Auth) -> Result<Json<u32>, Denied> {
         Ok(Json(
             query_as::<_, (u32,)>("SELECT COUNT(*) FROM cards")
                 .fetch_one(&self.db)
@@ -46,31 +68,41 @@ impl Api {
 
     /// Browse Cards
     #[oai(path = "/browse", method = "post")]
-    async fn browse(&self, data: Json<Browse>) -> Result<Json<Vec<Card>>> {
+    #[role(Worker)]
+    async fn browse(
+        &self,
+        auth: Auth,
+        data: Json<Browse<CardFilter>>,
+    ) -> Result<Json<Page<Card>>, (Denied, Browse<CardFilter>)> {
         let data = data.deref();
-        let skip = data.page_number() * data.count();
-        Ok(Json(
-            query_as::<_, Card>("SELECT * FROM cards LIMIT ?, ?")
-                .bind(skip)
-                .bind(data.count())
-                .fetch_all(&self.db)
-                .await
-                .context("browse cards")?,
-        ))
+        data.validate()?;
+        Ok(Json(data.fetch(&self.db, "cards").await?))
     }
 
     /// Create Card
     #[oai(path = "/", method = "post")]
-    async fn create(&self, mut data: Json<CreateCard>) -> Result<Json<u32>> {
-        data.validate()?;
-        let result =
-            query("INSERT INTO cards (cardType, number, expiration, owner) VALUES (?, ?, ?, ?)")
-                .bind(data.card_type as u32)
-                .bind(&data.number)
-                .bind(&data.expiration)
-                .bind(&data.owner)
-                .execute(&self.db)
-                .await;
+    #[role(Worker)]
+    async fn create(
+        &self,
+        auth: Auth,
+        mut data: Json<CreateCard>,
+    ) -> Result<Json<u32>, (Denied, CreateCard, codes::CardNumberAlreadyExists)> {
+        data.validate_with(&ValidationContext {
+            db: &self.db,
+            id: None,
+        })
+        .await?;
+        let result = query(
+            "INSERT INTO cards (cardType, number, expiration, owner, ownerId, bankId) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(data.card_type as u32)
+        .bind(&data.number)
+        .bind(&data.expiration)
+        .bind(&data.owner)
+        .bind(data.owner_id)
+        .bind(data.bank_id)
+        .execute(&self.db)
+        .await;
         if let Err(err) = &result {
             if let Some(err) = err.as_database_error() {
                 if err.kind() == ErrorKind::UniqueViolation {
@@ -82,9 +114,123 @@ impl Api {
         Ok(Json(result.last_insert_rowid() as u32))
     }
 
+    /// Update Card
+    #[oai(path = "/:id", method = "put")]
+    #[role(Worker)]
+    async fn update(
+        &self,
+        auth: Auth,
+        id: Path<u32>,
+        mut data: Json<CreateCard>,
+    ) -> Result<
+        (),
+        (
+            Denied,
+            CreateCard,
+            codes::CardNumberAlreadyExists,
+            codes::EntityNotExists,
+        ),
+    > {
+        data.validate_with(&ValidationContext {
+            db: &self.db,
+            id: Some(*id),
+        })
+        .await?;
+        let result = query(
+            "UPDATE cards SET cardType = ?, number = ?, expiration = ?, owner = ?, ownerId = ?, bankId = ? WHERE id = ?",
+        )
+        .bind(data.card_type as u32)
+        .bind(&data.number)
+        .bind(&data.expiration)
+        .bind(&data.owner)
+        .bind(data.owner_id)
+        .bind(data.bank_id)
+        .bind(*id)
+        .execute(&self.db)
+        .await;
+        if let Err(err) = &result {
+            if let Some(err) = err.as_database_error() {
+                if err.kind() == ErrorKind::UniqueViolation {
+                    return Err(CardNumberAlreadyExists(data.number.clone()).into());
+                }
+            }
+        }
+        let result = result.context("update card")?;
+        if result.rows_affected() == 0 {
+            Err(EntityNotExists("Card").into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Patch Card
+    #[oai(path = "/:id", method = "patch")]
+    #[role(Worker)]
+    async fn patch(
+        &self,
+        auth: Auth,
+        id: Path<u32>,
+        mut data: Json<PatchCard>,
+    ) -> Result<
+        (),
+        (
+            Denied,
+            PatchCard,
+            codes::CardNumberAlreadyExists,
+            codes::EntityNotExists,
+        ),
+    > {
+        data.validate_with(&ValidationContext {
+            db: &self.db,
+            id: Some(*id),
+        })
+        .await?;
+        let mut query = QueryBuilder::<Sqlite>::new("UPDATE cards SET id = id");
+        if let Some(card_type) = data.card_type {
+            query.push(", cardType = ").push_bind(card_type as u32);
+        }
+        if let Some(number) = &data.number {
+            query.push(", number = ").push_bind(number);
+        }
+        if let Some(expiration) = &data.expiration {
+            query.push(", expiration = ").push_bind(expiration);
+        }
+        if let Some(owner) = &data.owner {
+            query.push(", owner = ").push_bind(owner);
+        }
+        if let Some(owner_id) = data.owner_id {
+            query.push(", ownerId = ").push_bind(owner_id);
+        }
+        if let Some(bank_id) = data.bank_id {
+            query.push(", bankId = ").push_bind(bank_id);
+        }
+        query.push(" WHERE id = ").push_bind(*id);
+        let result = query.build().execute(&self.db).await;
+        if let Err(err) = &result {
+            if let Some(err) = err.as_database_error() {
+                if err.kind() == ErrorKind::UniqueViolation {
+                    return Err(
+                        CardNumberAlreadyExists(data.number.clone().unwrap_or_default()).into(),
+                    );
+                }
+            }
+        }
+        let result = result.context("patch card")?;
+        if result.rows_affected() == 0 {
+            Err(EntityNotExists("Card").into())
+        } else {
+            Ok(())
+        }
+    }
+
     /// Delete Card
     #[oai(path = "/:id", method = "delete")]
-    async fn delete(&self, id: Path<u32>) -> Result<()> {
+    #[role(Worker)]
+    async fn delete(
+        &self,
+        auth: Auth,
+        id: Path<u32>,
+    ) -> Result<(), (Denied, codes::EntityNotExists)> {
         let result = query("DELETE FROM cards WHERE id = ?")
             .bind(*id)
             .execute(&self.db)
@@ -0,0 +1,129 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+use bublik_macros::authorize;
+use poem_openapi::{payload::Json, ApiResponse, OpenApi};
+use sqlx::{query, Pool, Sqlite};
+use tokio::time::timeout;
+use tracing::warn;
+
+use crate::{
+    db::{migrate, SeedStatus},
+    models::health::{Check, CheckStatus, Health},
+};
+
+/// Time a single readiness check may take before it counts as failed
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct Api {
+    db: Pool<Sqlite>,
+    seed: SeedStatus,
+}
+
+pub fn api(db: &Pool<Sqlite>, seed: &SeedStatus) -> Api {
+    Api {
+        db: db.clone(),
+        seed: seed.clone(),
+    }
+}
+
+#[derive(ApiResponse)]
+pub enum ReadyResponse {
+    /// Every check passed
+    #[oai(status = 200)]
+    Ready(Json<Health>),
+    /// At least one check failed
+    #[oai(status = 503)]
+    NotReady(Json<Health>),
+}
+
+#[authorize]
+#[OpenApi(prefix_path = "/health", tag = "super::Tags::Health")]
+impl Api {
+    /// Liveness
+    ///
+    /// Answers as long as the process serves requests
+    #[oai(path = "/live", method = "get")]
+    async fn live(&self) -> Json<Health> {
+        Json(Health {
+            status: CheckStatus::Up,
+            checks: Vec::new(),
+        })
+    }
+
+    /// Readiness
+    ///
+    /// Checks that the database is reachable, migrations are applied and no seed is running
+    #[oai(path = "/ready", method = "get")]
+    async fn ready(&self) -> ReadyResponse {
+        let checks = vec![
+            check("database", "unreachable", async {
+                query("SELECT 1")
+                    .execute(&self.db)
+                    .await
+                    .context("query database")?;
+                Ok(())
+            })
+            .await,
+            check("migrations", "pending", async {
+                let pending = migrate::pending(&self.db).await?;
+                if !pending.is_empty() {
+                    bail!("pending migrations {pending:?}");
+                }
+                Ok(())
+            })
+            .await,
+            check("seed", "running", async {
+                if self.seed.is_seeding() {
+                    bail!("seeding in progress");
+                }
+                Ok(())
+            })
+            .await,
+        ];
+        if checks.iter().all(|check| check.status == CheckStatus::Up) {
+            ReadyResponse::Ready(Json(Health {
+                status: CheckStatus::Up,
+                checks,
+            }))
+        } else {
+            ReadyResponse::NotReady(Json(Health {
+                status: CheckStatus::Down,
+                checks,
+            }))
+        }
+    }
+}
+
+/// Runs `probe` with a timeout, failures are logged and answered only with `reason` as the
+/// probe is public
+async fn check(name: &str, reason: &str, probe: impl Future<Output = anyhow::Result<()>>) -> Check {
+    let start = Instant::now();
+    let result = match timeout(CHECK_TIMEOUT, probe).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(err)) => {
+            warn!(check = name, "readiness check failed: {err:#}");
+            Err(reason)
+        }
+        Err(_) => {
+            warn!(
+                check = name,
+                "readiness check timed out after {CHECK_TIMEOUT:?}"
+            );
+            Err("timed out")
+        }
+    };
+    Check {
+        name: name.to_owned(),
+        status: if result.is_ok() {
+            CheckStatus::Up
+        } else {
+            CheckStatus::Down
+        },
+        duration_ms: start.elapsed().as_secs_f64() * 1000.0,
+        error: result.err().map(str::to_owned),
+    }
+}
@@ -1,17 +1,28 @@
+pub mod admin;
 pub mod bank;
 pub mod card;
+pub mod health;
 pub mod user;
-pub mod validation;
+
+mod relations;
 
 mod prelude {
-    pub use crate::api::validation_error::ValidationError::*;
-    pub use poem::Result;
+    pub use super::relations::ensure_exists;
+    pub use crate::api::api_error::ApiResult as Result;
+    pub use crate::api::auth::Auth;
+    pub use crate::api::validation_error::{codes, ValidationError::*};
+    pub use crate::api::validation_rules::ValidationContext;
+    pub use bublik_macros::authorize;
+
+    /// Codes of callers without credentials or with an insufficient role
+    pub type Denied = (codes::Unauthorized, codes::Forbidden);
 }
 
 #[derive(poem_openapi::Tags)]
 pub enum Tags {
-    Validation,
     User,
     Card,
     Bank,
+    Admin,
+    Health,
 }
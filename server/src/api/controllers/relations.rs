@@ -0,0 +1,260 @@
+use anyhow::Context;
+use sqlx::{query, query_as, Pool, Sqlite, SqliteConnection};
+
+use super::prelude::*;
+use crate::{
+    api::validation_rules::exists,
+    config::{OnDelete, Relations},
+};
+
+/// Fails with `EntityNotExists` when `id` has no row in `table`
+pub async fn ensure_exists(
+    db: &Pool<Sqlite>,
+    table: &str,
+    entity: &'static str,
+    id: u32,
+) -> poem::Result<()> {
+    if exists(db, table, id).await? {
+        Ok(())
+    } else {
+        Err(EntityNotExists(entity).into())
+    }
+}
+
+/// Deletes a user and, depending on `relations`, the cards it owns
+pub async fn delete_user(
+    conn: &mut SqliteConnection,
+    id: u32,
+    relations: &Relations,
+) -> poem::Result<u64> {
+    apply(
+        conn,
+        "User",
+        "Card",
+        "cards",
+        "ownerId",
+        id,
+        relations.user_cards,
+    )
+    .await?;
+    Ok(query("DELETE FROM users WHERE id = ?")
+        .bind(id)
+        .execute(&mut *conn)
+        .await
+        .context("delete user")?
+        .rows_affected())
+}
+
+/// Deletes a bank and, depending on `relations`, its cards and users
+pub async fn delete_bank(
+    conn: &mut SqliteConnection,
+    id: u32,
+    relations: &Relations,
+) -> poem::Result<u64> {
+    apply(
+        conn,
+        "Bank",
+        "Card",
+        "cards",
+        "bankId",
+        id,
+        relations.bank_cards,
+    )
+    .await?;
+    match relations.bank_users {
+        OnDelete::Restrict => {
+            apply(
+                conn,
+                "Bank",
+                "User",
+                "users",
+                "bankId",
+                id,
+                OnDelete::Restrict,
+            )
+            .await?
+        }
+        OnDelete::Cascade => {
+            let users = query_as::<_, (u32,)>("SELECT id FROM users WHERE bankId = ?")
+                .bind(id)
+                .fetch_all(&mut *conn)
+                .await
+                .context("find bank users")?;
+            for (user,) in users {
+                delete_user(conn, user, relations).await?;
+            }
+        }
+    }
+    Ok(query("DELETE FROM banks WHERE id = ?")
+        .bind(id)
+        .execute(&mut *conn)
+        .await
+        .context("delete bank")?
+        .rows_affected())
+}
+
+/// Enforces `rule` on the rows of `table` referencing `id` through `column`
+async fn apply(
+    conn: &mut SqliteConnection,
+    entity: &'static str,
+    dependent: &'static str,
+    table: &str,
+    column: &str,
+    id: u32,
+    rule: OnDelete,
+) -> poem::Result<()> {
+    match rule {
+        OnDelete::Restrict => {
+            let count =
+                query_as::<_, (u32,)>(&format!("SELECT COUNT(*) FROM {table} WHERE {column} = ?"))
+                    .bind(id)
+                    .fetch_one(&mut *conn)
+                    .await
+                    .with_context(|| format!("count {table}"))?
+                    .0;
+            if count > 0 {
+                return Err(EntityHasDependents { entity, dependent }.into());
+            }
+        }
+        OnDelete::Cascade => {
+            query(&format!("DELETE FROM {table} WHERE {column} = ?"))
+                .bind(id)
+                .execute(&mut *conn)
+                .await
+                .with_context(|| format!("delete {table}"))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+    use crate::{api::validation_error::ValidationError, db::migrate};
+
+    /// In-memory database with banks 1 and 2, user 1 of bank 1 owning card 1 of bank 2 and user
+    /// 2 without a bank owning card 2 of bank 1
+    async fn database() -> Pool<Sqlite> {
+        // A single connection keeps the in-memory database alive
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        migrate::run(&db).await.unwrap();
+        for sql in [
+            "INSERT INTO banks (id, country, city, zipcode, street, buildingNumber) VALUES
+                (1, 'Poland', 'Warsaw', '00-001', 'Długa', '1'),
+                (2, 'Poland', 'Cracow', '30-001', 'Krótka', '2')",
+            "INSERT INTO users (id, firstName, lastName, email, phone, birthday, userType, bankId)
+                VALUES
+                (1, 'Adam', 'Nowak', 'adam@bublik.pl', '+48100200300', '1990-01-01', 3, 1),
+                (2, 'Ola', 'Nowak', 'ola@bublik.pl', '+48100200301', '1990-01-01', 3, NULL)",
+            "INSERT INTO cards (id, cardType, number, expiration, owner, ownerId, bankId) VALUES
+                (1, 1, '4111111111111111', '12/99', 'Adam Nowak', 1, 2),
+                (2, 1, '79927398713', '12/99', 'Ola Nowak', 2, 1)",
+        ] {
+            query(sql).execute(&db).await.unwrap();
+        }
+        db
+    }
+
+    async fn ids(db: &Pool<Sqlite>, table: &str) -> Vec<u32> {
+        query_as::<_, (u32,)>(&format!("SELECT id FROM {table} ORDER BY id"))
+            .fetch_all(db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(id,)| id)
+            .collect()
+    }
+
+    fn dependents(result: poem::Result<u64>) -> Option<(&'static str, &'static str)> {
+        match result.map_err(|err| err.downcast::<ValidationError>()) {
+            Err(Ok(EntityHasDependents { entity, dependent })) => Some((entity, dependent)),
+            _ => None,
+        }
+    }
+
+    fn relations(user_cards: OnDelete, bank_cards: OnDelete, bank_users: OnDelete) -> Relations {
+        Relations {
+            user_cards,
+            bank_cards,
+            bank_users,
+        }
+    }
+
+    #[tokio::test]
+    async fn ensures_rows_exist() {
+        let db = database().await;
+        assert!(ensure_exists(&db, "users", "User", 1).await.is_ok());
+        let err = ensure_exists(&db, "users", "User", 3).await.unwrap_err();
+        assert!(matches!(
+            err.downcast::<ValidationError>(),
+            Ok(EntityNotExists("User"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn restricts_deletes_with_dependents() {
+        let db = database().await;
+        let mut conn = db.acquire().await.unwrap();
+        let restrict = Relations::default();
+        assert_eq!(
+            dependents(delete_user(&mut conn, 1, &restrict).await),
+            Some(("User", "Card"))
+        );
+        assert_eq!(
+            dependents(delete_bank(&mut conn, 1, &restrict).await),
+            Some(("Bank", "Card"))
+        );
+        let cascade_cards = relations(OnDelete::Restrict, OnDelete::Cascade, OnDelete::Restrict);
+        assert_eq!(
+            dependents(delete_bank(&mut conn, 1, &cascade_cards).await),
+            Some(("Bank", "User"))
+        );
+        drop(conn);
+        // The cascaded card is deleted although the bank is not, callers roll back on errors
+        assert_eq!(ids(&db, "banks").await, [1, 2]);
+        assert_eq!(ids(&db, "users").await, [1, 2]);
+        assert_eq!(ids(&db, "cards").await, [1]);
+    }
+
+    #[tokio::test]
+    async fn cascades_user_cards() {
+        let db = database().await;
+        let mut conn = db.acquire().await.unwrap();
+        let cascade = relations(OnDelete::Cascade, OnDelete::Restrict, OnDelete::Restrict);
+        assert_eq!(delete_user(&mut conn, 1, &cascade).await.unwrap(), 1);
+        assert_eq!(delete_user(&mut conn, 1, &cascade).await.unwrap(), 0);
+        drop(conn);
+        assert_eq!(ids(&db, "users").await, [2]);
+        assert_eq!(ids(&db, "cards").await, [2]);
+    }
+
+    #[tokio::test]
+    async fn cascades_bank_cards_and_users() {
+        let db = database().await;
+        let mut conn = db.acquire().await.unwrap();
+        let cascade = relations(OnDelete::Cascade, OnDelete::Cascade, OnDelete::Cascade);
+        assert_eq!(delete_bank(&mut conn, 1, &cascade).await.unwrap(), 1);
+        drop(conn);
+        // User 1 goes with bank 1 and takes its card of bank 2 along
+        assert_eq!(ids(&db, "banks").await, [2]);
+        assert_eq!(ids(&db, "users").await, [2]);
+        assert!(ids(&db, "cards").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn cascaded_users_keep_their_restrictions() {
+        let db = database().await;
+        let mut conn = db.acquire().await.unwrap();
+        let cascade_users = relations(OnDelete::Restrict, OnDelete::Cascade, OnDelete::Cascade);
+        assert_eq!(
+            dependents(delete_bank(&mut conn, 1, &cascade_users).await),
+            Some(("User", "Card"))
+        );
+    }
+}
@@ -2,39 +2,88 @@ use std::ops::Deref;
 
 use anyhow::Context;
 use poem_openapi::{param::Path, payload::Json, OpenApi};
-use sqlx::{error::ErrorKind, query, query_as, Pool, Sqlite};
+use sqlx::{error::ErrorKind, query, query_as, Pool, QueryBuilder, Sqlite};
 
-use super::prelude::*;
-use crate::models::{
-    browse::Browse,
-    user::{CreateUser, User},
+use super::{prelude::*, relations};
+use crate::{
+    config::{Config, Relations},
+    models::{
+        browse::{Browse, Page},
+        card::Card,
+        user::{CreateUser, PatchUser, User, UserFilter},
+    },
 };
 
 pub struct Api {
     db: Pool<Sqlite>,
+    relations: Relations,
 }
 
-pub fn api(db: &Pool<Sqlite>) -> Api {
-    Api { db: db.clone() }
+pub fn api(db: &Pool<Sqlite>, config: &Config) -> Api {
+    Api {
+        db: db.clone(),
+        relations: config.relations,
+    }
 }
 
+#[authorize]
 #[OpenApi(prefix_path = "/user", tag = "super::Tags::User")]
 impl Api {
     /// Get User
     #[oai(path = "/:id", method = "get")]
-    async fn get(&self, id: Path<u32>) -> Result<Json<User>> {
+    #[role(Worker, owner = "*id")]
+    async fn get(
+        &self,
+        auth: Auth,
+        id: Path<u32>,
+    ) -> Result<Json<User>, (Denied, codes::EntityNotExists)> {
         query_as::<_, User>("SELECT * FROM users WHERE id = ?")
             .bind(*id)
             .fetch_optional(&self.db)
             .await
             .context("get user")?
-            .map(|o| Json(o))
+            .map(Json)
+            .ok_or(EntityNotExists("User").into())
+    }
+
+    /// Get Current User
+    #[oai(path = "/me", method = "get")]
+    #[role(Customer)]
+    async fn me(
+        &self,
+        auth: Auth,
+    ) -> Result<Json<User>, (codes::Unauthorized, codes::EntityNotExists)> {
+        query_as::<_, User>("SELECT * FROM users WHERE id = ?")
+            .bind(auth.identity().user_id)
+            .fetch_optional(&self.db)
+            .await
+            .context("get current user")?
+            .map(Json)
             .ok_or(EntityNotExists("User").into())
     }
 
+    /// Get User Cards
+    #[oai(path = "/:id/cards", method = "get")]
+    #[role(Worker, owner = "*id")]
+    async fn cards(
+        &self,
+        auth: Auth,
+        id: Path<u32>,
+    ) -> Result<Json<Vec<Card>>, (Denied, codes::EntityNotExists)> {
+        ensure_exists(&self.db, "users", "User", *id).await?;
+        Ok(Json(
+            query_as::<_, Card>("SELECT * FROM cards WHERE ownerId = ? ORDER BY id")
+                .bind(*id)
+                .fetch_all(&self.db)
+                .await
+                .context("get user cards")?,
+        ))
+    }
+
     /// Count Users
     #[oai(path = "/count", method = "get")]
-    async fn count(&self) -> Result<Json<u32>> {
+    #[role(Worker)]
+    async fn count(&self, auth: Auth) -> Result<Json<u32>, Denied> {
         Ok(Json(
             query_as::<_, (u32,)>("SELECT COUNT(*) FROM users")
                 .fetch_one(&self.db)
@@ -46,30 +95,38 @@ impl Api {
 
     /// Browse Users
     #[oai(path = "/browse", method = "post")]
-    async fn browse(&self, data: Json<Browse>) -> Result<Json<Vec<User>>> {
+    #[role(Worker)]
+    async fn browse(
+        &self,
+        auth: Auth,
+        data: Json<Browse<UserFilter>>,
+    ) -> Result<Json<Page<User>>, (Denied, Browse<UserFilter>)> {
         let data = data.deref();
-        let skip = data.page_number() * data.count();
-        Ok(Json(
-            query_as::<_, User>("SELECT * FROM users LIMIT ?, ?")
-                .bind(skip)
-                .bind(data.count())
-                .fetch_all(&self.db)
-                .await
-                .context("browse users")?,
-        ))
+        data.validate()?;
+        Ok(Json(data.fetch(&self.db, "users").await?))
     }
 
     /// Create User
     #[oai(path = "/", method = "post")]
-    async fn create(&self, mut data: Json<CreateUser>) -> Result<Json<u32>> {
-        data.validate()?;
-        let result = query("INSERT INTO users (firstName, lastName, email, phone, birthday, userType) VALUES (?, ?, ?, ?, ?, ?)")
+    #[role(Manager)]
+    async fn create(
+        &self,
+        auth: Auth,
+        mut data: Json<CreateUser>,
+    ) -> Result<Json<u32>, (Denied, CreateUser, codes::UserEmailAlreadyExists)> {
+        data.validate_with(&ValidationContext {
+            db: &self.db,
+            id: None,
+        })
+        .await?;
+        let result = query("INSERT INTO users (firstName, lastName, email, phone, birthday, userType, bankId) VALUES (?, ?, ?, ?, ?, ?, ?)")
             .bind(&data.first_name)
             .bind(&data.last_name)
             .bind(&data.email)
             .bind(&data.phone)
-            .bind(&data.birthday)
+            .bind(data.birthday)
             .bind(data.user_type as u32)
+            .bind(data.bank_id)
             .execute(&self.db)
             .await;
         if let Err(err) = &result {
@@ -83,18 +140,132 @@ impl Api {
         Ok(Json(result.last_insert_rowid() as u32))
     }
 
-    /// Delete User
-    #[oai(path = "/:id", method = "delete")]
-    async fn delete(&self, id: Path<u32>) -> Result<()> {
-        let result = query("DELETE FROM users WHERE id = ?")
+    /// Update User
+    #[oai(path = "/:id", method = "put")]
+    #[role(Manager)]
+    async fn update(
+        &self,
+        auth: Auth,
+        id: Path<u32>,
+        mut data: Json<CreateUser>,
+    ) -> Result<
+        (),
+        (
+            Denied,
+            CreateUser,
+            codes::UserEmailAlreadyExists,
+            codes::EntityNotExists,
+        ),
+    > {
+        data.validate_with(&ValidationContext {
+            db: &self.db,
+            id: Some(*id),
+        })
+        .await?;
+        let result = query("UPDATE users SET firstName = ?, lastName = ?, email = ?, phone = ?, birthday = ?, userType = ?, bankId = ? WHERE id = ?")
+            .bind(&data.first_name)
+            .bind(&data.last_name)
+            .bind(&data.email)
+            .bind(&data.phone)
+            .bind(data.birthday)
+            .bind(data.user_type as u32)
+            .bind(data.bank_id)
             .bind(*id)
             .execute(&self.db)
-            .await
-            .context("delete user")?;
+            .await;
+        if let Err(err) = &result {
+            if let Some(err) = err.as_database_error() {
+                if err.kind() == ErrorKind::UniqueViolation {
+                    return Err(UserEmailAlreadyExists(data.email.clone()).into());
+                }
+            }
+        }
+        let result = result.context("update user")?;
         if result.rows_affected() == 0 {
             Err(EntityNotExists("User").into())
         } else {
             Ok(())
         }
     }
+
+    /// Patch User
+    #[oai(path = "/:id", method = "patch")]
+    #[role(Manager)]
+    async fn patch(
+        &self,
+        auth: Auth,
+        id: Path<u32>,
+        mut data: Json<PatchUser>,
+    ) -> Result<
+        (),
+        (
+            Denied,
+            PatchUser,
+            codes::UserEmailAlreadyExists,
+            codes::EntityNotExists,
+        ),
+    > {
+        data.validate_with(&ValidationContext {
+            db: &self.db,
+            id: Some(*id),
+        })
+        .await?;
+        let mut query = QueryBuilder::<Sqlite>::new("UPDATE users SET id = id");
+        if let Some(first_name) = &data.first_name {
+            query.push(", firstName = ").push_bind(first_name);
+        }
+        if let Some(last_name) = &data.last_name {
+            query.push(", lastName = ").push_bind(last_name);
+        }
+        if let Some(email) = &data.email {
+            query.push(", email = ").push_bind(email);
+        }
+        if let Some(phone) = &data.phone {
+            query.push(", phone = ").push_bind(phone);
+        }
+        if let Some(birthday) = &data.birthday {
+            query.push(", birthday = ").push_bind(birthday);
+        }
+        if let Some(user_type) = data.user_type {
+            query.push(", userType = ").push_bind(user_type as u32);
+        }
+        if let Some(bank_id) = data.bank_id {
+            query.push(", bankId = ").push_bind(bank_id);
+        }
+        query.push(" WHERE id = ").push_bind(*id);
+        let result = query.build().execute(&self.db).await;
+        if let Err(err) = &result {
+            if let Some(err) = err.as_database_error() {
+                if err.kind() == ErrorKind::UniqueViolation {
+                    return Err(
+                        UserEmailAlreadyExists(data.email.clone().unwrap_or_default()).into(),
+                    );
+                }
+            }
+        }
+        let result = result.context("patch user")?;
+        if result.rows_affected() == 0 {
+            Err(EntityNotExists("User").into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Delete User
+    #[oai(path = "/:id", method = "delete")]
+    #[role(Manager)]
+    async fn delete(
+        &self,
+        auth: Auth,
+        id: Path<u32>,
+    ) -> Result<(), (Denied, codes::EntityNotExists, codes::EntityHasDependents)> {
+        let mut tx = self.db.begin().await.context("begin delete user")?;
+        let deleted = relations::delete_user(&mut tx, *id, &self.relations).await?;
+        tx.commit().await.context("commit delete user")?;
+        if deleted == 0 {
+            Err(EntityNotExists("User").into())
+        } else {
+            Ok(())
+        }
+    }
 }
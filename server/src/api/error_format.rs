@@ -0,0 +1,104 @@
+use async_trait::async_trait;
+use poem::{http::header, Endpoint, Error, IntoResponse, Middleware, Request, Response};
+
+use super::{
+    messages::Language,
+    problem::{self, Problem},
+    request_id::RequestId,
+    validation_error::{error_response, BodyFormat, ValidationError, ValidationErrors},
+};
+
+const ERROR_FORMAT_HEADER: &str = "X-Error-Format";
+const ERROR_PARAMETERS_HEADER: &str = "X-Error-Parameters";
+
+/// Reduces aggregated validation errors to the first one for clients sending
+/// `X-Error-Format: single`, names parameters for `X-Error-Parameters: named`, adds
+/// messages for clients sending `Accept-Language`, renders every error as
+/// `application/problem+json` for clients preferring it in `Accept` and quotes the request id
+/// in every rendered error
+#[derive(Default)]
+pub struct ErrorFormat;
+
+impl<E: Endpoint> Middleware<E> for ErrorFormat {
+    type Output = ErrorFormatEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        ErrorFormatEndpoint { inner: ep }
+    }
+}
+
+pub struct ErrorFormatEndpoint<E> {
+    inner: E,
+}
+
+#[async_trait]
+impl<E: Endpoint> Endpoint for ErrorFormatEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let single = req
+            .header(ERROR_FORMAT_HEADER)
+            .is_some_and(|format| format.eq_ignore_ascii_case("single"));
+        let request_id = req.extensions().get::<RequestId>().map(|id| id.0.clone());
+        let format = BodyFormat {
+            language: req.header(header::ACCEPT_LANGUAGE).map(Language::negotiate),
+            named: req
+                .header(ERROR_PARAMETERS_HEADER)
+                .is_some_and(|parameters| parameters.eq_ignore_ascii_case("named")),
+            request_id: request_id.as_deref(),
+        };
+        let instance = req
+            .header(header::ACCEPT)
+            .is_some_and(problem::accepts)
+            .then(|| req.uri().path().to_owned());
+        let err = match self.inner.call(req).await {
+            Ok(res) => {
+                let res = res.into_response();
+                // Caught panics come back as bare statuses
+                if !res.status().is_server_error()
+                    || res.headers().contains_key(header::CONTENT_TYPE)
+                {
+                    return Ok(res);
+                }
+                ValidationError::Internal.into()
+            }
+            Err(err) => err,
+        };
+        Err(match instance {
+            Some(instance) => {
+                let problem = Problem {
+                    instance: Some(instance),
+                    ..problem(err, single, format)
+                };
+                Error::from_response(problem.response())
+            }
+            None => reformat(err, single, format),
+        })
+    }
+}
+
+fn reformat(err: Error, single: bool, format: BodyFormat<'_>) -> Error {
+    let response = match err.downcast::<ValidationErrors>() {
+        Ok(errors) if single => errors.into_first().response(format),
+        Ok(errors) => errors.response(format),
+        Err(err) => match err.downcast::<ValidationError>() {
+            Ok(error) => error.response(format),
+            Err(err) => error_response(&err, format),
+        },
+    };
+    Error::from_response(response)
+}
+
+fn problem(err: Error, single: bool, format: BodyFormat<'_>) -> Problem {
+    match err.downcast::<ValidationErrors>() {
+        Ok(errors) if single => errors.into_first().problem(format),
+        Ok(errors) => errors.problem(format),
+        Err(err) => match err.downcast::<ValidationError>() {
+            Ok(error) => error.problem(format),
+            Err(err) => Problem {
+                request_id: format.request_id.map(str::to_owned),
+                ..Problem::from_error(&err)
+            },
+        },
+    }
+}
@@ -0,0 +1,228 @@
+use serde_json::Value;
+
+use super::validation_error::ValidationError;
+
+/// Languages of the message catalog
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Language {
+    En,
+    Pl,
+}
+
+impl Language {
+    /// Best supported match of an `Accept-Language` header, English when none matches
+    pub fn negotiate(header: &str) -> Self {
+        let mut best = (Self::En, 0.0);
+        for range in header.split(',') {
+            let mut parts = range.split(';').map(str::trim);
+            let tag = parts.next().unwrap_or_default();
+            let quality = parts
+                .find_map(|part| part.strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.parse::<f32>().ok())
+                .unwrap_or(0.0);
+            let primary = tag.split('-').next().unwrap_or_default();
+            let language = if primary.eq_ignore_ascii_case("en") {
+                Self::En
+            } else if primary.eq_ignore_ascii_case("pl") {
+                Self::Pl
+            } else {
+                continue;
+            };
+            if quality > best.1 {
+                best = (language, quality);
+            }
+        }
+        best.0
+    }
+}
+
+/// Renders the catalog template of `error`, filling in its named parameters
+pub fn message(error: &ValidationError, language: Language) -> String {
+    let (en, pl) = templates(error);
+    let template = match language {
+        Language::En => en,
+        Language::Pl => pl,
+    };
+    let parameters = error.named_parameters();
+    let mut message = String::new();
+    let mut rest = template;
+    while let Some((start, end)) = rest
+        .find('{')
+        .and_then(|start| Some((start, start + rest[start..].find('}')?)))
+    {
+        message.push_str(&rest[..start]);
+        let mut placeholder = rest[start + 1..end].split('|');
+        let name = placeholder.next().unwrap_or_default();
+        let forms: Vec<_> = placeholder.collect();
+        match parameters.iter().find(|(parameter, _)| *parameter == name) {
+            Some((_, value)) if forms.is_empty() => message.push_str(&display(value)),
+            Some((_, value)) => {
+                let form = plural(language, value).min(forms.len() - 1);
+                message.push_str(forms[form]);
+            }
+            None => message.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    message.push_str(rest);
+    message
+}
+
+/// Index of the plural form of `value`: one or other in English, one, few or many in Polish
+fn plural(language: Language, value: &Value) -> usize {
+    let Some(n) = value.as_u64() else {
+        return usize::MAX;
+    };
+    match language {
+        _ if n == 1 => 0,
+        Language::En => 1,
+        Language::Pl if (2..=4).contains(&(n % 10)) && !(12..=14).contains(&(n % 100)) => 1,
+        Language::Pl => 2,
+    }
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::Null => "…".to_owned(),
+        Value::String(value) => value.clone(),
+        Value::Array(values) => values.iter().map(display).collect::<Vec<_>>().join(", "),
+        value => value.to_string(),
+    }
+}
+
+/// English and Polish template of every error, `{name}` refers to a named parameter and
+/// `{name|one|few|many}` to the plural form matching it
+fn templates(error: &ValidationError) -> (&'static str, &'static str) {
+    use ValidationError::*;
+    match error {
+        Unknown => ("Unknown error", "Nieznany błąd"),
+        MinLength { .. } => (
+            "{field} must be at least {min} {min|character|characters} long",
+            "Pole {field} musi mieć co najmniej {min} {min|znak|znaki|znaków}",
+        ),
+        MaxLength { .. } => (
+            "{field} must be at most {max} {max|character|characters} long",
+            "Pole {field} może mieć najwyżej {max} {max|znak|znaki|znaków}",
+        ),
+        Pattern { .. } => (
+            "{field} has an invalid format: {value}",
+            "Pole {field} ma nieprawidłowy format: {value}",
+        ),
+        NotBlank { .. } => (
+            "{field} must not be blank",
+            "Pole {field} nie może być puste",
+        ),
+        Email { .. } => (
+            "{value} is not a valid email address",
+            "{value} nie jest poprawnym adresem e-mail",
+        ),
+        Phone { .. } => (
+            "{value} is not a valid phone number, expected E.164 format",
+            "{value} nie jest poprawnym numerem telefonu, oczekiwano formatu E.164",
+        ),
+        Luhn { .. } => (
+            "{value} is not a valid card number",
+            "{value} nie jest poprawnym numerem karty",
+        ),
+        OneOf { .. } => (
+            "{field} must be one of: {allowed}",
+            "Pole {field} musi mieć jedną z wartości: {allowed}",
+        ),
+        Range { .. } => (
+            "{field} must be within [{min}, {max}]",
+            "Pole {field} musi mieścić się w przedziale [{min}, {max}]",
+        ),
+        Past { .. } => (
+            "{field} must be in the past",
+            "Pole {field} musi być datą z przeszłości",
+        ),
+        Future { .. } => (
+            "{field} must be in the future",
+            "Pole {field} musi być datą z przyszłości",
+        ),
+        MinAge { .. } => (
+            "Age must be at least {min} {min|year|years}",
+            "Wiek musi wynosić co najmniej {min} {min|rok|lata|lat}",
+        ),
+        EntityNotExists(_) => ("{0} does not exist", "{0} nie istnieje"),
+        ReferenceNotExists(_) => (
+            "Referenced {0} does not exist",
+            "Wskazany obiekt {0} nie istnieje",
+        ),
+        EntityHasDependents { .. } => (
+            "{entity} is still referenced by {dependent} records",
+            "{entity} ma powiązane rekordy {dependent}",
+        ),
+        UserEmailAlreadyExists(_) => (
+            "Email {0} is already taken",
+            "Adres e-mail {0} jest już zajęty",
+        ),
+        CardNumberAlreadyExists(_) => (
+            "Card number {0} is already taken",
+            "Numer karty {0} jest już zajęty",
+        ),
+        CardExpired(_) => ("Card expired in {0}", "Karta straciła ważność w {0}"),
+        UnknownSortField(_) => ("Cannot sort by {0}", "Nie można sortować po {0}"),
+        InvalidCursor(_) => ("Invalid cursor {0}", "Nieprawidłowy kursor {0}"),
+        Unauthorized => (
+            "Missing or invalid credentials",
+            "Brak lub nieprawidłowe dane uwierzytelniające",
+        ),
+        Forbidden(_) => ("Requires the {0} role", "Wymagana rola {0}"),
+        Internal => ("Internal server error", "Wewnętrzny błąd serwera"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn min_length(min: usize, language: Language) -> String {
+        message(&ValidationError::MinLength { field: "name", min }, language)
+    }
+
+    #[test]
+    fn negotiate_picks_highest_quality() {
+        let cases = [
+            ("pl", Language::Pl),
+            ("PL-pl, en;q=0.8", Language::Pl),
+            ("pl;q=0.5, en", Language::En),
+            ("de, pl;q=0.1", Language::Pl),
+            ("de, fr", Language::En),
+            ("pl;q=0", Language::En),
+            ("pl;q=abc", Language::En),
+        ];
+        for (header, language) in cases {
+            assert_eq!(Language::negotiate(header), language, "{header}");
+        }
+    }
+
+    #[test]
+    fn polish_plural_forms() {
+        let forms = [
+            (1, "znak"),
+            (3, "znaki"),
+            (5, "znaków"),
+            (12, "znaków"),
+            (22, "znaki"),
+        ];
+        for (min, form) in forms {
+            assert_eq!(
+                min_length(min, Language::Pl),
+                format!("Pole name musi mieć co najmniej {min} {form}")
+            );
+        }
+    }
+
+    #[test]
+    fn english_plural_forms() {
+        assert_eq!(
+            min_length(1, Language::En),
+            "name must be at least 1 character long"
+        );
+        assert_eq!(
+            min_length(3, Language::En),
+            "name must be at least 3 characters long"
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex, PoisonError},
+    time::Instant,
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use poem::{endpoint::make, Endpoint, IntoResponse, Middleware, Request, Response};
+use sqlx::{query_as, Pool, Sqlite};
+
+use super::{
+    route_templates::RouteTemplates,
+    validation_error::{ValidationError, ValidationErrors},
+};
+
+/// Upper bounds of the latency histogram buckets, in seconds
+const BUCKETS: [f64; 11] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// Request and error counters, recorded as a middleware and rendered in Prometheus text format
+#[derive(Clone)]
+pub struct Metrics {
+    templates: RouteTemplates,
+    recorded: Arc<Mutex<Recorded>>,
+}
+
+#[derive(Default)]
+struct Recorded {
+    /// Keyed by method, route template and status
+    requests: BTreeMap<(String, String, u16), u64>,
+    /// Keyed by method and route template
+    latencies: BTreeMap<(String, String), Histogram>,
+    /// Keyed by error code
+    errors: BTreeMap<String, u64>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations per bucket, not cumulative
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+impl Metrics {
+    pub fn new(templates: RouteTemplates) -> Self {
+        Self {
+            templates,
+            recorded: Arc::default(),
+        }
+    }
+
+    /// Serves `/metrics` in Prometheus text format
+    pub fn endpoint(&self, db: &Pool<Sqlite>) -> impl Endpoint {
+        let (metrics, db) = (self.clone(), db.clone());
+        make(move |_| {
+            let (metrics, db) = (metrics.clone(), db.clone());
+            async move {
+                let text = metrics.render(&db).await.map_err(poem::Error::from)?;
+                Ok::<_, poem::Error>(text.with_content_type("text/plain; version=0.0.4"))
+            }
+        })
+    }
+
+    fn record(&self, method: String, route: String, status: u16, seconds: f64, codes: Vec<String>) {
+        let mut recorded = self.recorded.lock().unwrap_or_else(PoisonError::into_inner);
+        let key = (method, route);
+        recorded
+            .latencies
+            .entry(key.clone())
+            .or_default()
+            .observe(seconds);
+        *recorded.requests.entry((key.0, key.1, status)).or_default() += 1;
+        for code in codes {
+            *recorded.errors.entry(code).or_default() += 1;
+        }
+    }
+
+    /// Recorded metrics followed by connection pool statistics and row counts of every table
+    async fn render(&self, db: &Pool<Sqlite>) -> anyhow::Result<String> {
+        let tables: Vec<(String,)> = query_as(
+            "SELECT name FROM sqlite_master WHERE type = 'table' \
+             AND name NOT LIKE 'sqlite_%' AND name NOT LIKE '_sqlx_%' ORDER BY name",
+        )
+        .fetch_all(db)
+        .await
+        .context("list tables")?;
+        let mut rows = Vec::with_capacity(tables.len());
+        for (table,) in tables {
+            let (count,): (i64,) = query_as(&format!("SELECT COUNT(*) FROM \"{table}\""))
+                .fetch_one(db)
+                .await
+                .with_context(|| format!("count {table}"))?;
+            rows.push((table, count));
+        }
+
+        let mut out = String::new();
+        let recorded = self.recorded.lock().unwrap_or_else(PoisonError::into_inner);
+        header(
+            &mut out,
+            "bublik_http_requests_total",
+            "counter",
+            "Handled requests",
+        )?;
+        for ((method, route, status), count) in &recorded.requests {
+            let labels = labels(&[
+                ("method", method),
+                ("route", route),
+                ("status", &status.to_string()),
+            ]);
+            writeln!(out, "bublik_http_requests_total{labels} {count}")?;
+        }
+        let name = "bublik_http_request_duration_seconds";
+        header(&mut out, name, "histogram", "Request latency")?;
+        for ((method, route), histogram) in &recorded.latencies {
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let labels = labels(&[
+                    ("method", method),
+                    ("route", route),
+                    ("le", &bound.to_string()),
+                ]);
+                writeln!(out, "{name}_bucket{labels} {cumulative}")?;
+            }
+            let labels_inf = labels(&[("method", method), ("route", route), ("le", "+Inf")]);
+            writeln!(out, "{name}_bucket{labels_inf} {}", histogram.count)?;
+            let labels = labels(&[("method", method), ("route", route)]);
+            writeln!(out, "{name}_sum{labels} {}", histogram.sum)?;
+            writeln!(out, "{name}_count{labels} {}", histogram.count)?;
+        }
+        header(
+            &mut out,
+            "bublik_errors_total",
+            "counter",
+            "Errors answered, by code",
+        )?;
+        for (code, count) in &recorded.errors {
+            writeln!(
+                out,
+                "bublik_errors_total{} {count}",
+                labels(&[("code", code)])
+            )?;
+        }
+        drop(recorded);
+
+        let name = "bublik_db_pool_connections";
+        header(&mut out, name, "gauge", "Open database connections")?;
+        let idle = db.num_idle();
+        let active = (db.size() as usize).saturating_sub(idle);
+        writeln!(out, "{name}{} {idle}", labels(&[("state", "idle")]))?;
+        writeln!(out, "{name}{} {active}", labels(&[("state", "active")]))?;
+        let name = "bublik_db_pool_max_connections";
+        header(
+            &mut out,
+            name,
+            "gauge",
+            "Maximum number of database connections",
+        )?;
+        writeln!(out, "{name} {}", db.options().get_max_connections())?;
+        header(&mut out, "bublik_db_rows", "gauge", "Rows per table")?;
+        for (table, count) in rows {
+            writeln!(
+                out,
+                "bublik_db_rows{} {count}",
+                labels(&[("table", &table)])
+            )?;
+        }
+        Ok(out)
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) -> std::fmt::Result {
+    writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}")
+}
+
+fn labels(labels: &[(&str, &str)]) -> String {
+    let labels: Vec<_> = labels
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{name}=\"{value}\"")
+        })
+        .collect();
+    format!("{{{}}}", labels.join(","))
+}
+
+/// Codes of an error answered by the API, every error of an aggregate counts
+fn codes(err: &poem::Error) -> Vec<String> {
+    if let Some(errors) = err.downcast_ref::<ValidationErrors>() {
+        errors.0.iter().map(ToString::to_string).collect()
+    } else if let Some(error) = err.downcast_ref::<ValidationError>() {
+        vec![error.to_string()]
+    } else {
+        Vec::new()
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for Metrics {
+    type Output = MetricsEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        MetricsEndpoint {
+            inner: ep,
+            metrics: self.clone(),
+        }
+    }
+}
+
+pub struct MetricsEndpoint<E> {
+    inner: E,
+    metrics: Metrics,
+}
+
+#[async_trait]
+impl<E: Endpoint> Endpoint for MetricsEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let start = Instant::now();
+        let method = req.method().to_string();
+        let route = self
+            .metrics
+            .templates
+            .matching(req.uri().path())
+            .unwrap_or("unmatched")
+            .to_owned();
+        let result = self.inner.call(req).await.map(IntoResponse::into_response);
+        let (status, codes) = match &result {
+            Ok(res) => (res.status(), Vec::new()),
+            Err(err) => (err.status(), codes(err)),
+        };
+        let seconds = start.elapsed().as_secs_f64();
+        self.metrics
+            .record(method, route, status.as_u16(), seconds, codes);
+        result
+    }
+}
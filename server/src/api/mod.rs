@@ -2,24 +2,75 @@ use poem::{
     http::StatusCode, middleware::CatchPanic, Endpoint, EndpointExt, IntoEndpoint, Middleware,
     Route,
 };
-use poem_openapi::OpenApiService;
+use poem_openapi::{OpenApi, OpenApiService};
 use sqlx::{Pool, Sqlite};
 use tracing::error;
 
+use crate::{config::Config, db::SeedStatus};
+use route_templates::RouteTemplates;
+
+pub mod access_log;
+pub mod api_error;
+pub mod auth;
 pub mod controllers;
+pub mod error_format;
+pub mod messages;
+pub mod metrics;
+pub mod problem;
+pub mod request_id;
+pub mod route_templates;
 pub mod trace_error;
 pub mod validation_error;
+pub mod validation_rules;
 
-pub fn routes(db: &Pool<Sqlite>) -> impl IntoEndpoint {
+pub fn routes(db: &Pool<Sqlite>, config: &Config, seed: &SeedStatus) -> impl IntoEndpoint {
     use controllers::*;
-    let controllers = (validation::Api, user::api(db), card::api(db), bank::api(db));
-    let api = OpenApiService::new(controllers, "Klaudia", "1.0");
-    let docs = api.swagger_ui();
-    Route::new()
-        .nest("/", api)
-        .nest("/swagger", docs)
+    let controllers = (
+        user::api(db, config),
+        card::api(db),
+        bank::api(db, config),
+        health::api(db, seed),
+    );
+    let (mut route, templates) = if config.admin {
+        mount((controllers, admin::api(db, config, seed)), db, config)
+    } else {
+        mount(controllers, db, config)
+    };
+    let metrics = metrics::Metrics::new(templates.clone());
+    if config.metrics {
+        route = route.at("/metrics", metrics.endpoint(db));
+    }
+    route
         .with(catch_panic())
         .with(trace_error::TraceError)
+        .with(metrics)
+        .with(error_format::ErrorFormat)
+        .with(access_log::AccessLog::new(templates))
+        .with(request_id::TraceRequest)
+}
+
+fn mount<T: OpenApi + 'static>(
+    controllers: T,
+    db: &Pool<Sqlite>,
+    config: &Config,
+) -> (Route, RouteTemplates) {
+    let api = OpenApiService::new(controllers, "Klaudia", "1.0");
+    let mut route = Route::new();
+    let mut plain = Vec::new();
+    if config.swagger {
+        plain.extend(["/swagger", "/openapi.json"]);
+        route = route
+            .nest("/swagger", api.swagger_ui())
+            .at("/openapi.json", api.spec_endpoint());
+    }
+    if config.metrics {
+        plain.push("/metrics");
+    }
+    let route = route.nest(
+        "/",
+        api.with(auth::Authentication::new(db, config.jwt_secret.as_deref()).public("/health")),
+    );
+    (route, RouteTemplates::of::<T>(&plain))
 }
 
 fn catch_panic<E: Endpoint>() -> impl Middleware<E> {
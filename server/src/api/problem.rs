@@ -0,0 +1,118 @@
+use poem::{http::StatusCode, IntoResponse, Response};
+use poem_openapi::{payload::Json, Object};
+
+use super::validation_error::{FieldErrorBody, NamedParameters, Parameters};
+
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// RFC 9457 problem details, `code` and the parameters extend the standard members
+#[derive(Object)]
+#[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
+pub struct Problem {
+    /// `urn:bublik:error:<code>`, `about:blank` for errors without a code
+    #[oai(rename = "type")]
+    pub problem_type: String,
+    /// Reason phrase of `status`
+    pub title: String,
+    pub status: u16,
+    /// Description of this occurrence, absent for server errors
+    pub detail: Option<String>,
+    /// Path of the rejected request
+    pub instance: Option<String>,
+    pub code: Option<String>,
+    pub parameters: Option<Parameters>,
+    pub named_parameters: Option<NamedParameters>,
+    /// All failed checks of a rejected payload
+    pub errors: Option<Vec<FieldErrorBody>>,
+    /// `X-Request-Id` of the request, to be quoted when reporting the problem
+    pub request_id: Option<String>,
+}
+
+impl Problem {
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            problem_type: "about:blank".to_owned(),
+            title: status.canonical_reason().unwrap_or_default().to_owned(),
+            status: status.as_u16(),
+            detail: None,
+            instance: None,
+            code: None,
+            parameters: None,
+            named_parameters: None,
+            errors: None,
+            request_id: None,
+        }
+    }
+
+    /// Problem of an error without a code, server errors keep their details to the log
+    pub fn from_error(err: &poem::Error) -> Self {
+        let status = err.status();
+        Self {
+            detail: status.is_client_error().then(|| err.to_string()),
+            ..Self::new(status)
+        }
+    }
+
+    pub fn with_code(status: StatusCode, code: String) -> Self {
+        Self {
+            problem_type: format!("urn:bublik:error:{code}"),
+            code: Some(code),
+            ..Self::new(status)
+        }
+    }
+
+    pub fn response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        Json(self)
+            .with_content_type(PROBLEM_CONTENT_TYPE)
+            .with_status(status)
+            .into_response()
+    }
+}
+
+/// Whether an `Accept` header prefers problem details over plain JSON
+pub fn accepts(header: &str) -> bool {
+    let quality = |media_type: &str| {
+        header
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';').map(str::trim);
+                let tag = parts.next().unwrap_or_default();
+                tag.eq_ignore_ascii_case(media_type).then(|| {
+                    parts
+                        .find_map(|part| part.strip_prefix("q="))
+                        .map_or(Some(1.0), |q| q.parse::<f32>().ok())
+                        .unwrap_or(0.0)
+                })
+            })
+            .fold(0.0, f32::max)
+    };
+    let problem = quality(PROBLEM_CONTENT_TYPE);
+    problem > 0.0 && problem >= quality("application/json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_by_quality() {
+        let cases = [
+            ("application/problem+json", true),
+            ("application/json", false),
+            ("*/*", false),
+            ("application/json, application/problem+json", true),
+            ("application/json, application/problem+json;q=0.5", false),
+            ("application/json;q=0.5, application/problem+json", true),
+            (
+                "application/problem+json; q=0.9, application/json; q=0.9",
+                true,
+            ),
+            ("application/problem+json;q=0", false),
+            ("APPLICATION/PROBLEM+JSON", true),
+        ];
+        for (header, expected) in cases {
+            assert_eq!(accepts(header), expected, "{header}");
+        }
+    }
+}
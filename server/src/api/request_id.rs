@@ -0,0 +1,65 @@
+use async_trait::async_trait;
+use poem::{http::HeaderValue, Endpoint, IntoResponse, Middleware, Request, Response};
+use tracing::{error_span, Instrument};
+
+/// Lower case, as required for inserting into a header map
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+const MAX_LENGTH: usize = 128;
+
+/// Identifier of the current request, inserted into request extensions by [`TraceRequest`]
+#[derive(Clone)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// Identifier sent by the client when it is printable ASCII of sane length, a new one otherwise
+    fn from_header(header: Option<&str>) -> Self {
+        match header {
+            Some(id)
+                if !id.is_empty()
+                    && id.len() <= MAX_LENGTH
+                    && id.bytes().all(|byte| byte.is_ascii_graphic()) =>
+            {
+                Self(id.to_owned())
+            }
+            _ => Self(format!("{:032x}", rand::random::<u128>())),
+        }
+    }
+}
+
+/// Accepts or generates an `X-Request-Id`, logs the request within a span carrying it and
+/// echoes it in the response headers
+#[derive(Default)]
+pub struct TraceRequest;
+
+impl<E: Endpoint> Middleware<E> for TraceRequest {
+    type Output = TraceRequestEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        TraceRequestEndpoint { inner: ep }
+    }
+}
+
+pub struct TraceRequestEndpoint<E> {
+    inner: E,
+}
+
+#[async_trait]
+impl<E: Endpoint> Endpoint for TraceRequestEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        let id = RequestId::from_header(req.header(REQUEST_ID_HEADER));
+        // Error level keeps the span, and so the id, on every line that passes the filter
+        let span = error_span!("request", id = %id.0);
+        let header = HeaderValue::from_str(&id.0).ok();
+        req.extensions_mut().insert(id);
+        let mut res = match self.inner.call(req).instrument(span).await {
+            Ok(res) => res.into_response(),
+            Err(err) => err.into_response(),
+        };
+        if let Some(header) = header {
+            res.headers_mut().insert(REQUEST_ID_HEADER, header);
+        }
+        Ok(res)
+    }
+}
@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use poem_openapi::OpenApi;
+
+/// Path templates of the served routes, e.g. `/user/{id}`, labelling requests in logs
+#[derive(Clone, Default)]
+pub struct RouteTemplates(Arc<[String]>);
+
+impl RouteTemplates {
+    /// Templates of every operation of `T` followed by `extra` plain routes
+    pub fn of<T: OpenApi>(extra: &[&str]) -> Self {
+        let paths = T::meta()
+            .into_iter()
+            .flat_map(|api| api.paths)
+            .map(|path| path.path);
+        Self(
+            paths
+                .chain(extra.iter().map(|path| (*path).to_owned()))
+                .collect(),
+        )
+    }
+
+    /// Template matching `path`, literal segments win over parameters
+    pub fn matching(&self, path: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .filter_map(|template| literals(template, path).map(|literals| (literals, template)))
+            .max_by_key(|(literals, _)| *literals)
+            .map(|(_, template)| template.as_str())
+    }
+}
+
+/// Number of literal segments when `template` matches `path`
+fn literals(template: &str, path: &str) -> Option<usize> {
+    let mut segments = path.trim_end_matches('/').split('/');
+    let mut literals = 0;
+    for expected in template.trim_end_matches('/').split('/') {
+        let segment = segments.next()?;
+        if expected.starts_with('{') && expected.ends_with('}') {
+            if segment.is_empty() {
+                return None;
+            }
+        } else if expected == segment {
+            literals += 1;
+        } else {
+            return None;
+        }
+    }
+    segments.next().is_none().then_some(literals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_prefers_literal_segments() {
+        let templates = RouteTemplates(
+            [
+                "/user/{id}",
+                "/user/me",
+                "/user/{id}/cards",
+                "/user",
+                "/swagger",
+            ]
+            .map(str::to_owned)
+            .into(),
+        );
+        let cases = [
+            ("/user/5", Some("/user/{id}")),
+            ("/user/me", Some("/user/me")),
+            ("/user/5/cards", Some("/user/{id}/cards")),
+            ("/user/", Some("/user")),
+            ("/swagger", Some("/swagger")),
+            ("/user//cards", None),
+            ("/user/5/banks", None),
+            ("/bank", None),
+        ];
+        for (path, template) in cases {
+            assert_eq!(templates.matching(path), template, "{path}");
+        }
+    }
+}
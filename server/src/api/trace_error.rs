@@ -1,7 +1,9 @@
 use async_trait::async_trait;
-use poem::{Endpoint, Error, IntoResponse, Middleware, Request, Response};
+use poem::{Endpoint, IntoResponse, Middleware, Request, Response};
 use tracing::error;
 
+use super::validation_error::ValidationError;
+
 #[derive(Default)]
 pub struct TraceError;
 
@@ -29,7 +31,7 @@ impl<E: Endpoint> Endpoint for TraceErrorEndpoint<E> {
                     Err(err)
                 } else {
                     error!("{}", err);
-                    Err(Error::from_status(err.status()))
+                    Err(ValidationError::Internal.into())
                 }
             }
         }
@@ -1,37 +1,358 @@
 use bublik_macros::{DisplayUpperSnake, JsonParameters, ResponseEnum};
+use chrono::NaiveDate;
 use poem::{error::ResponseError, http::StatusCode, IntoResponse, Response};
 use poem_openapi::{payload::Json, Object};
 use serde_json::Value;
+use std::collections::BTreeMap;
+
+use super::{
+    messages::{message, Language},
+    problem::Problem,
+};
+use crate::models::user::UserType;
 
 #[derive(Debug, DisplayUpperSnake, ResponseEnum, JsonParameters, thiserror::Error)]
 pub enum ValidationError {
     Unknown,
-    MinLength { field: &'static str, min: usize },
-    MaxLength { field: &'static str, max: usize },
-    Pattern { field: &'static str, value: String },
+    MinLength {
+        field: &'static str,
+        min: usize,
+    },
+    MaxLength {
+        field: &'static str,
+        max: usize,
+    },
+    Pattern {
+        field: &'static str,
+        value: String,
+    },
+    NotBlank {
+        field: &'static str,
+    },
+    Email {
+        field: &'static str,
+        value: String,
+    },
+    Phone {
+        field: &'static str,
+        value: String,
+    },
+    Luhn {
+        field: &'static str,
+        value: String,
+    },
+    OneOf {
+        field: &'static str,
+        value: String,
+        allowed: Vec<&'static str>,
+    },
+    Range {
+        field: &'static str,
+        min: Option<Value>,
+        max: Option<Value>,
+    },
+    Past {
+        field: &'static str,
+        value: NaiveDate,
+    },
+    Future {
+        field: &'static str,
+        value: NaiveDate,
+    },
+    MinAge {
+        field: &'static str,
+        min: u32,
+    },
+    /// Entity identified by the path is missing
+    #[status(404)]
     EntityNotExists(&'static str),
+    /// Entity referenced from the payload is missing
+    ReferenceNotExists(&'static str),
+    #[status(409)]
+    EntityHasDependents {
+        entity: &'static str,
+        dependent: &'static str,
+    },
+    #[status(409)]
     UserEmailAlreadyExists(String),
+    #[status(409)]
     CardNumberAlreadyExists(String),
+    CardExpired(String),
+    UnknownSortField(String),
+    InvalidCursor(String),
+    #[status(401)]
+    Unauthorized,
+    #[status(403)]
+    Forbidden(UserType),
+    #[status(500)]
+    Internal,
+}
+
+/// Every failed check of a validated payload, never empty
+#[derive(Debug, thiserror::Error)]
+#[error("{}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+pub struct ValidationErrors(pub Vec<ValidationError>);
+
+pub type Parameters = Vec<Option<Value>>;
+pub type NamedParameters = BTreeMap<String, Value>;
+
+/// Client preferences for rendering error bodies
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct BodyFormat<'a> {
+    /// Language of `message`, no message when unknown
+    pub language: Option<Language>,
+    /// `namedParameters` instead of positional `parameters`
+    pub named: bool,
+    /// Identifier of the request, quoted back in `requestId`
+    pub request_id: Option<&'a str>,
 }
 
 #[derive(Object)]
 #[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
 pub struct ValidationErrorBody {
     code: String,
-    parameters: Option<Vec<Option<Value>>>,
+    parameters: Option<Parameters>,
+    /// Parameters keyed by name, described per code by the `ValidationError` schema
+    named_parameters: Option<NamedParameters>,
+    /// Description in the language picked from `Accept-Language`, only sent with that header
+    message: Option<String>,
+    /// All failed checks, `code` and `parameters` repeat the first one
+    errors: Option<Vec<FieldErrorBody>>,
+    /// `X-Request-Id` of the request, to be quoted when reporting the error
+    request_id: Option<String>,
+}
+
+#[derive(Object)]
+#[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
+pub struct FieldErrorBody {
+    field: Option<String>,
+    code: String,
+    parameters: Option<Parameters>,
+    named_parameters: Option<NamedParameters>,
+    message: Option<String>,
 }
 
 impl ResponseError for ValidationError {
     fn status(&self) -> StatusCode {
-        StatusCode::BAD_REQUEST
+        ValidationError::status(self)
     }
 
     fn as_response(&self) -> Response {
+        self.response(BodyFormat::default())
+    }
+}
+
+impl ValidationError {
+    pub fn response(&self, format: BodyFormat<'_>) -> Response {
+        let (parameters, named_parameters) = self.formatted_parameters(format);
         Json(ValidationErrorBody {
             code: self.to_string(),
-            parameters: self.parameters(),
+            parameters,
+            named_parameters,
+            message: self.message(format),
+            errors: None,
+            request_id: format.request_id.map(str::to_owned),
+        })
+        .with_status(self.status())
+        .into_response()
+    }
+
+    /// Problem details of the error, `detail` in English unless a language was negotiated
+    pub fn problem(&self, format: BodyFormat<'_>) -> Problem {
+        let (parameters, named_parameters) = self.formatted_parameters(format);
+        Problem {
+            detail: Some(message(self, format.language.unwrap_or(Language::En))),
+            parameters,
+            named_parameters,
+            request_id: format.request_id.map(str::to_owned),
+            ..Problem::with_code(self.status(), self.to_string())
+        }
+    }
+
+    fn formatted_parameters(
+        &self,
+        format: BodyFormat<'_>,
+    ) -> (Option<Parameters>, Option<NamedParameters>) {
+        if !format.named {
+            return (self.parameters(), None);
+        }
+        let named: BTreeMap<_, _> = self
+            .named_parameters()
+            .into_iter()
+            .map(|(name, value)| (name.to_owned(), value))
+            .collect();
+        (None, (!named.is_empty()).then_some(named))
+    }
+
+    fn message(&self, format: BodyFormat<'_>) -> Option<String> {
+        format.language.map(|language| message(self, language))
+    }
+}
+
+/// Body of an error raised outside validation, such as a malformed payload, an unknown route or
+/// a disallowed method, coded after its status and detailed like problem details
+pub fn error_response(err: &poem::Error, format: BodyFormat<'_>) -> Response {
+    let status = err.status();
+    let detail = status
+        .is_client_error()
+        .then(|| Value::String(err.to_string()));
+    let (parameters, named_parameters) = match detail {
+        Some(detail) if format.named => {
+            (None, Some(BTreeMap::from([("detail".to_owned(), detail)])))
+        }
+        Some(detail) => (Some(vec![Some(detail)]), None),
+        None => (None, None),
+    };
+    Json(ValidationErrorBody {
+        code: status_code(status),
+        parameters,
+        named_parameters,
+        message: None,
+        errors: None,
+        request_id: format.request_id.map(str::to_owned),
+    })
+    .with_status(status)
+    .into_response()
+}
+
+/// Upper snake reason phrase of a status, e.g. `METHOD_NOT_ALLOWED`
+fn status_code(status: StatusCode) -> String {
+    status
+        .canonical_reason()
+        .unwrap_or("Error")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+impl ValidationErrors {
+    pub fn check(errors: Vec<ValidationError>) -> poem::Result<()> {
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Self(errors).into())
+        }
+    }
+
+    /// Leading error alone, for clients expecting a single error
+    pub fn into_first(mut self) -> ValidationError {
+        match self.lead() {
+            Some(lead) => self.0.swap_remove(lead),
+            None => ValidationError::Unknown,
+        }
+    }
+
+    /// Index of the error repeated at the top, the first one sent with the aggregated status
+    fn lead(&self) -> Option<usize> {
+        let status = self.status();
+        self.0.iter().position(|error| error.status() == status)
+    }
+
+    fn first(&self) -> &ValidationError {
+        self.lead()
+            .map_or(&ValidationError::Unknown, |lead| &self.0[lead])
+    }
+
+    pub fn response(&self, format: BodyFormat<'_>) -> Response {
+        let first = self.first();
+        let (parameters, named_parameters) = first.formatted_parameters(format);
+        Json(ValidationErrorBody {
+            code: first.to_string(),
+            parameters,
+            named_parameters,
+            message: first.message(format),
+            errors: Some(self.bodies(format)),
+            request_id: format.request_id.map(str::to_owned),
         })
         .with_status(self.status())
         .into_response()
     }
+
+    /// Problem details of the first error listing all of them in `errors`
+    pub fn problem(&self, format: BodyFormat<'_>) -> Problem {
+        let first = self.first();
+        let status = self.status();
+        Problem {
+            title: status.canonical_reason().unwrap_or_default().to_owned(),
+            status: status.as_u16(),
+            errors: Some(self.bodies(format)),
+            ..first.problem(format)
+        }
+    }
+
+    fn bodies(&self, format: BodyFormat<'_>) -> Vec<FieldErrorBody> {
+        self.0
+            .iter()
+            .map(|error| {
+                let (parameters, named_parameters) = error.formatted_parameters(format);
+                FieldErrorBody {
+                    field: error.field(),
+                    code: error.to_string(),
+                    parameters,
+                    named_parameters,
+                    message: error.message(format),
+                }
+            })
+            .collect()
+    }
+}
+
+impl ResponseError for ValidationErrors {
+    /// Status shared by all errors, 400 when they differ and any of them is sent with it
+    fn status(&self) -> StatusCode {
+        let mut statuses = self.0.iter().map(ValidationError::status);
+        let first = statuses.next().unwrap_or(StatusCode::BAD_REQUEST);
+        if statuses.all(|status| status == first) {
+            first
+        } else if self
+            .0
+            .iter()
+            .any(|error| error.status() == StatusCode::BAD_REQUEST)
+        {
+            StatusCode::BAD_REQUEST
+        } else {
+            first
+        }
+    }
+
+    fn as_response(&self) -> Response {
+        self.response(BodyFormat::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregated_status() {
+        let conflicts = ValidationErrors(vec![
+            ValidationError::UserEmailAlreadyExists("a@b.pl".to_owned()),
+            ValidationError::CardNumberAlreadyExists("4111".to_owned()),
+        ]);
+        assert_eq!(conflicts.status(), StatusCode::CONFLICT);
+        let mixed = ValidationErrors(vec![
+            ValidationError::UserEmailAlreadyExists("a@b.pl".to_owned()),
+            ValidationError::ReferenceNotExists("Bank"),
+        ]);
+        assert_eq!(mixed.status(), StatusCode::BAD_REQUEST);
+        // The top level code is documented under the status it is sent with
+        assert_eq!(mixed.first().to_string(), "REFERENCE_NOT_EXISTS");
+        assert_eq!(mixed.into_first().to_string(), "REFERENCE_NOT_EXISTS");
+        let unrelated = ValidationErrors(vec![
+            ValidationError::EntityHasDependents {
+                entity: "Bank",
+                dependent: "Card",
+            },
+            ValidationError::EntityNotExists("Bank"),
+        ]);
+        assert_eq!(unrelated.status(), StatusCode::CONFLICT);
+        assert_eq!(unrelated.first().to_string(), "ENTITY_HAS_DEPENDENTS");
+    }
 }
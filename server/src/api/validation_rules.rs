@@ -0,0 +1,442 @@
+use anyhow::Context;
+use chrono::{Datelike, NaiveDate, Utc};
+use sqlx::{query, Executor, Pool, Sqlite};
+
+use super::validation_error::ValidationError;
+
+/// State available to `#[val(check = "...")]` rules
+pub struct ValidationContext<'a> {
+    pub db: &'a Pool<Sqlite>,
+    /// Entity being replaced or patched, `None` on create
+    pub id: Option<u32>,
+}
+
+/// Schema pattern equivalent of [`is_phone`]
+pub const PHONE_PATTERN: &str = r"^\+[1-9][0-9]{0,14}$";
+/// Schema pattern of the digits [`is_luhn`] accepts, the checksum cannot be expressed
+pub const LUHN_PATTERN: &str = "^[0-9]+$";
+
+/// `local@domain.tld` without whitespace
+pub fn is_email(value: &str) -> bool {
+    let Some((local, domain)) = value.rsplit_once('@') else {
+        return false;
+    };
+    let labels: Vec<_> = domain.split('.').collect();
+    !local.is_empty()
+        && !value.chars().any(char::is_whitespace)
+        && labels.len() > 1
+        && labels.iter().all(|label| !label.is_empty())
+}
+
+/// E.164 number, `+` followed by up to 15 digits without a leading zero
+pub fn is_phone(value: &str) -> bool {
+    let Some(digits) = value.strip_prefix('+') else {
+        return false;
+    };
+    (1..=15).contains(&digits.len())
+        && digits.bytes().all(|b| b.is_ascii_digit())
+        && !digits.starts_with('0')
+}
+
+/// Digits only, passing the Luhn checksum
+pub fn is_luhn(value: &str) -> bool {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return false;
+    }
+    let sum: u32 = value
+        .bytes()
+        .rev()
+        .enumerate()
+        .map(|(i, b)| {
+            let digit = u32::from(b - b'0');
+            match i % 2 {
+                0 => digit,
+                _ if digit > 4 => digit * 2 - 9,
+                _ => digit * 2,
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+pub fn today() -> NaiveDate {
+    Utc::now().date_naive()
+}
+
+/// Full years since `birthday`
+pub fn age(birthday: NaiveDate) -> u32 {
+    let today = today();
+    let years = today.year() - birthday.year();
+    let had_birthday = (today.month(), today.day()) >= (birthday.month(), birthday.day());
+    u32::try_from(if had_birthday { years } else { years - 1 }).unwrap_or(0)
+}
+
+/// `ReferenceNotExists` when `id` has no row in `table`
+pub async fn referenced(
+    ctx: &ValidationContext<'_>,
+    table: &str,
+    entity: &'static str,
+    id: u32,
+) -> anyhow::Result<Option<ValidationError>> {
+    Ok((!exists(ctx.db, table, id).await?).then_some(ValidationError::ReferenceNotExists(entity)))
+}
+
+/// Whether `table` has a row with `id`
+pub async fn exists<'e>(
+    db: impl Executor<'e, Database = Sqlite>,
+    table: &str,
+    id: u32,
+) -> anyhow::Result<bool> {
+    Ok(query(&format!("SELECT 1 FROM {table} WHERE id = ?"))
+        .bind(id)
+        .fetch_optional(db)
+        .await
+        .with_context(|| format!("find {table} {id}"))?
+        .is_some())
+}
+
+/// Whether a row of `table` other than `except` holds `value` in `column`
+pub async fn taken(
+    db: &Pool<Sqlite>,
+    table: &str,
+    column: &str,
+    value: &str,
+    except: Option<u32>,
+) -> anyhow::Result<bool> {
+    Ok(query(&format!(
+        "SELECT 1 FROM {table} WHERE {column} = ? AND id IS NOT ?"
+    ))
+    .bind(value)
+    .bind(except)
+    .fetch_optional(db)
+    .await
+    .with_context(|| format!("find {table} {column}"))?
+    .is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Days, Months};
+    use poem_openapi::{
+        registry::{MetaSchemaRef, Registry},
+        Object,
+    };
+    use serde_json::json;
+    use sqlx::{sqlite::SqlitePoolOptions, Pool, Sqlite};
+
+    use super::*;
+    use crate::{
+        api::{
+            api_error::ErrorCodes,
+            validation_error::{ValidationError, ValidationErrors},
+        },
+        db::migrate,
+    };
+    use bublik_macros::Validation;
+
+    #[test]
+    fn email() {
+        assert!(is_email("adam.nowak@bublik.pl"));
+        assert!(is_email("a@b.c"));
+        assert!(!is_email("adam.nowak"));
+        assert!(!is_email("@bublik.pl"));
+        assert!(!is_email("adam@localhost"));
+        assert!(!is_email("adam@bublik..pl"));
+        assert!(!is_email("adam nowak@bublik.pl"));
+    }
+
+    #[test]
+    fn phone() {
+        assert!(is_phone("+48100200300"));
+        assert!(is_phone("+1"));
+        assert!(is_phone("+123456789012345"));
+        assert!(!is_phone("48100200300"));
+        assert!(!is_phone("+"));
+        assert!(!is_phone("+0100200300"));
+        assert!(!is_phone("+1234567890123456"));
+        assert!(!is_phone("+48 100 200 300"));
+    }
+
+    #[test]
+    fn luhn() {
+        assert!(is_luhn("4111111111111111"));
+        assert!(is_luhn("79927398713"));
+        assert!(is_luhn("0"));
+        assert!(!is_luhn("4111111111111112"));
+        assert!(!is_luhn("79927398710"));
+        assert!(!is_luhn(""));
+        assert!(!is_luhn("4111 1111 1111 1111"));
+    }
+
+    #[test]
+    fn full_years() {
+        let years_ago = |years: u32| today() - Months::new(12 * years);
+        assert_eq!(age(today()), 0);
+        assert_eq!(age(years_ago(18)), 18);
+        assert_eq!(age(years_ago(18) + Days::new(1)), 17);
+        assert_eq!(age(years_ago(18) - Days::new(1)), 18);
+        assert_eq!(age(today() + Days::new(1)), 0);
+    }
+
+    /// Payload using every field rule and hook kind
+    #[derive(Object, Validation)]
+    #[oai(rename_all = "camelCase")]
+    #[val(
+        trim,
+        rule(path = "expires_after_birthday", codes(Future)),
+        check(path = "card_free", codes(CardNumberAlreadyExists))
+    )]
+    struct Everything {
+        #[val(length = "name_length", not_blank)]
+        first_name: String,
+        #[val(rule(path = "capitalized", codes(Pattern)))]
+        last_name: Option<String>,
+        #[val(pattern = "^[A-Z]{2}$", custom(path = "known_country", codes(OneOf)))]
+        country: String,
+        #[val(email, check(path = "email_free", codes(UserEmailAlreadyExists)))]
+        email: String,
+        #[val(phone)]
+        phone: Option<String>,
+        #[val(luhn)]
+        card: String,
+        #[val(one_of("red", "green"))]
+        color: String,
+        #[val(range(min = 1, max = 10))]
+        count: u32,
+        #[val(range(min = -1.5))]
+        balance: Option<f64>,
+        #[val(past, min_age = 18)]
+        birthday: NaiveDate,
+        #[val(future)]
+        expires: NaiveDate,
+    }
+
+    fn name_length() -> (usize, usize) {
+        (2, 8)
+    }
+
+    fn capitalized(value: &str) -> Result<(), ValidationError> {
+        match value.starts_with(char::is_uppercase) {
+            true => Ok(()),
+            false => Err(ValidationError::Pattern {
+                field: "lastName",
+                value: value.to_owned(),
+            }),
+        }
+    }
+
+    fn known_country(field: &'static str, value: &str) -> Result<(), ValidationError> {
+        const KNOWN: &[&str] = &["PL", "DE"];
+        match KNOWN.contains(&value) {
+            true => Ok(()),
+            false => Err(ValidationError::OneOf {
+                field,
+                value: value.to_owned(),
+                allowed: KNOWN.to_vec(),
+            }),
+        }
+    }
+
+    async fn email_free(
+        value: &str,
+        ctx: &ValidationContext<'_>,
+    ) -> anyhow::Result<Option<ValidationError>> {
+        Ok(taken(ctx.db, "users", "email", value, ctx.id)
+            .await?
+            .then(|| ValidationError::UserEmailAlreadyExists(value.to_owned())))
+    }
+
+    fn expires_after_birthday(value: &Everything) -> Result<(), ValidationError> {
+        match value.expires > value.birthday {
+            true => Ok(()),
+            false => Err(ValidationError::Future {
+                field: "expires",
+                value: value.expires,
+            }),
+        }
+    }
+
+    async fn card_free(
+        value: &Everything,
+        ctx: &ValidationContext<'_>,
+    ) -> anyhow::Result<Option<ValidationError>> {
+        Ok(taken(ctx.db, "cards", "number", &value.card, ctx.id)
+            .await?
+            .then(|| ValidationError::CardNumberAlreadyExists(value.card.clone())))
+    }
+
+    /// In-memory database holding user `adam@bublik.pl` with card `4111111111111111`
+    async fn database() -> Pool<Sqlite> {
+        // A single connection keeps the in-memory database alive
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        migrate::run(&db).await.unwrap();
+        query(
+            "INSERT INTO users (id, firstName, lastName, email, phone, birthday, userType)
+                VALUES (1, 'Adam', 'Nowak', 'adam@bublik.pl', '+48100200300', '1990-01-01', 1)",
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        query(
+            "INSERT INTO cards (id, cardType, number, expiration, owner, ownerId)
+                VALUES (1, 1, '4111111111111111', '12/99', 'Adam Nowak', 1)",
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        db
+    }
+
+    fn valid() -> Everything {
+        Everything {
+            first_name: " Ola ".to_owned(),
+            last_name: Some("Nowak".to_owned()),
+            country: "PL".to_owned(),
+            email: "ola@bublik.pl".to_owned(),
+            phone: Some("+48100200301".to_owned()),
+            card: "79927398713".to_owned(),
+            color: "red".to_owned(),
+            count: 10,
+            balance: Some(-1.5),
+            birthday: today() - Months::new(12 * 18),
+            expires: today() + Days::new(1),
+        }
+    }
+
+    async fn failures(value: &mut Everything) -> Vec<(Option<String>, String)> {
+        let db = database().await;
+        let ctx = ValidationContext { db: &db, id: None };
+        let Err(err) = value.validate_with(&ctx).await else {
+            return Vec::new();
+        };
+        err.downcast_ref::<ValidationErrors>()
+            .unwrap()
+            .0
+            .iter()
+            .map(|error| (error.field(), error.to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn derive_accepts_valid_payload() {
+        let mut value = valid();
+        assert_eq!(failures(&mut value).await, []);
+        assert_eq!(value.first_name, "Ola");
+    }
+
+    #[tokio::test]
+    async fn derive_reports_every_rule_in_order() {
+        let mut value = Everything {
+            first_name: "   ".to_owned(),
+            last_name: Some("nowak".to_owned()),
+            country: "pl".to_owned(),
+            email: "adam@bublik.pl".to_owned(),
+            phone: Some("100200300".to_owned()),
+            card: "4111111111111111".to_owned(),
+            color: "blue".to_owned(),
+            count: 0,
+            balance: Some(-2.0),
+            birthday: today(),
+            expires: today(),
+        };
+        let failed = failures(&mut value).await;
+        let failed: Vec<_> = failed
+            .iter()
+            .map(|(field, code)| (field.as_deref(), code.as_str()))
+            .collect();
+        assert_eq!(
+            failed,
+            [
+                (Some("firstName"), "NOT_BLANK"),
+                (Some("firstName"), "MIN_LENGTH"),
+                (Some("country"), "PATTERN"),
+                (Some("country"), "ONE_OF"),
+                (Some("phone"), "PHONE"),
+                (Some("color"), "ONE_OF"),
+                (Some("count"), "RANGE"),
+                (Some("balance"), "RANGE"),
+                (Some("birthday"), "PAST"),
+                (Some("birthday"), "MIN_AGE"),
+                (Some("expires"), "FUTURE"),
+                (Some("lastName"), "PATTERN"),
+                (Some("expires"), "FUTURE"),
+                (None, "USER_EMAIL_ALREADY_EXISTS"),
+                (None, "CARD_NUMBER_ALREADY_EXISTS"),
+            ]
+        );
+
+        value = Everything {
+            email: "adam".to_owned(),
+            card: "4111111111111112".to_owned(),
+            ..valid()
+        };
+        assert_eq!(
+            failures(&mut value).await,
+            [
+                (Some("email".to_owned()), "EMAIL".to_owned()),
+                (Some("card".to_owned()), "LUHN".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn derive_declares_field_and_hook_codes() {
+        let mut codes: Vec<_> = Everything::codes().iter().map(|code| code.code).collect();
+        codes.sort_unstable();
+        assert_eq!(
+            codes,
+            [
+                "CARD_NUMBER_ALREADY_EXISTS",
+                "EMAIL",
+                "FUTURE",
+                "LUHN",
+                "MAX_LENGTH",
+                "MIN_AGE",
+                "MIN_LENGTH",
+                "NOT_BLANK",
+                "ONE_OF",
+                "PAST",
+                "PATTERN",
+                "PHONE",
+                "RANGE",
+                "USER_EMAIL_ALREADY_EXISTS",
+            ]
+        );
+    }
+
+    #[test]
+    fn derive_writes_rules_into_schema() {
+        let mut registry = Registry::new();
+        <Everything as ErrorCodes>::register(&mut registry);
+        let schema = &registry.schemas["Everything"];
+        let property = |name: &str| {
+            let (_, property) = schema
+                .properties
+                .iter()
+                .find(|(field, _)| *field == name)
+                .unwrap();
+            match property {
+                MetaSchemaRef::Inline(property) => property.clone(),
+                MetaSchemaRef::Reference(_) => panic!("{name} is not inline"),
+            }
+        };
+        let first_name = property("firstName");
+        assert_eq!(
+            (first_name.min_length, first_name.max_length),
+            (Some(2), Some(8))
+        );
+        assert_eq!(property("country").pattern.as_deref(), Some("^[A-Z]{2}$"));
+        assert_eq!(property("email").format, Some("email"));
+        assert_eq!(property("phone").pattern.as_deref(), Some(PHONE_PATTERN));
+        assert_eq!(property("card").pattern.as_deref(), Some(LUHN_PATTERN));
+        assert_eq!(property("color").enum_items, [json!("red"), json!("green")]);
+        let count = property("count");
+        assert_eq!((count.minimum, count.maximum), (Some(1.0), Some(10.0)));
+        let balance = property("balance");
+        assert_eq!((balance.minimum, balance.maximum), (Some(-1.5), None));
+    }
+}
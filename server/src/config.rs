@@ -0,0 +1,260 @@
+use std::{
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, Parser, Subcommand};
+use serde::Deserialize;
+use tracing_subscriber::filter::Targets;
+
+use crate::models::seed::SeedMode;
+
+const DEFAULT_CONFIG: &str = "bublik.toml";
+
+#[derive(Parser)]
+#[command(version, about = "Primitive and simple REST API")]
+pub struct Cli {
+    #[command(flatten)]
+    pub config: ConfigArgs,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Manage the database schema without starting the server
+    Migrate {
+        #[command(subcommand)]
+        command: MigrateCommand,
+    },
+    /// Load fixtures into the database without starting the server
+    Seed {
+        #[arg(long, value_enum, default_value_t)]
+        mode: SeedMode,
+        /// Directory with fixture files, defaults to the data directory
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+    /// Issue credentials for the API
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum MigrateCommand {
+    /// Apply pending migrations
+    Run,
+    /// List migrations and whether they are applied
+    Status,
+}
+
+#[derive(Subcommand)]
+pub enum AuthCommand {
+    /// Create an API key for a user, the key is printed only once
+    Key {
+        #[arg(long)]
+        user: u32,
+        /// Label to tell keys apart
+        #[arg(long)]
+        name: String,
+    },
+    /// Sign a bearer token for a user
+    Token {
+        #[arg(long)]
+        user: u32,
+        /// Lifetime in seconds
+        #[arg(long, default_value_t = 3600)]
+        ttl: u64,
+    },
+}
+
+/// Command line flags, each falling back to a `BUBLIK_*` environment variable
+/// and then to the configuration file
+#[derive(Args)]
+pub struct ConfigArgs {
+    /// Configuration file, `bublik.toml` is used when present
+    #[arg(long, env = "BUBLIK_CONFIG")]
+    config: Option<PathBuf>,
+    /// Address the HTTP server binds to
+    #[arg(long, env = "BUBLIK_LISTEN")]
+    listen: Option<String>,
+    /// SQLite database URL
+    #[arg(long, env = "BUBLIK_DATABASE_URL")]
+    database_url: Option<String>,
+    /// Directory with seed data JSON files
+    #[arg(long, env = "BUBLIK_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+    /// One of `off`, `error`, `warn`, `info`, `debug` or `trace`, optionally followed by
+    /// per-module levels such as `info,sqlx=warn,access=off`
+    #[arg(long, env = "BUBLIK_LOG_LEVEL")]
+    log_level: Option<String>,
+    /// One of `text` or `json`
+    #[arg(long, env = "BUBLIK_LOG_FORMAT")]
+    log_format: Option<String>,
+    /// Maximum number of database connections
+    #[arg(long, env = "BUBLIK_POOL_SIZE")]
+    pool_size: Option<u32>,
+    /// Serve Swagger UI under `/swagger`
+    #[arg(long, env = "BUBLIK_SWAGGER")]
+    swagger: Option<bool>,
+    /// Serve Prometheus metrics under `/metrics`
+    #[arg(long, env = "BUBLIK_METRICS")]
+    metrics: Option<bool>,
+    /// Serve admin endpoints under `/admin`
+    #[arg(long, env = "BUBLIK_ADMIN")]
+    admin: Option<bool>,
+    /// Secret signing bearer tokens, at least 32 bytes
+    #[arg(long, env = "BUBLIK_JWT_SECRET", hide_env_values = true)]
+    jwt_secret: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    listen: Option<String>,
+    database_url: Option<String>,
+    data_dir: Option<PathBuf>,
+    pool_size: Option<u32>,
+    swagger: Option<bool>,
+    metrics: Option<bool>,
+    admin: Option<bool>,
+    jwt_secret: Option<String>,
+    log: FileLogConfig,
+    relations: Relations,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileLogConfig {
+    level: Option<String>,
+    format: Option<String>,
+}
+
+pub struct Config {
+    pub listen: SocketAddr,
+    pub database_url: String,
+    pub data_dir: PathBuf,
+    pub pool_size: u32,
+    pub swagger: bool,
+    pub metrics: bool,
+    pub admin: bool,
+    pub jwt_secret: Option<String>,
+    pub log: LogConfig,
+    pub relations: Relations,
+}
+
+pub struct LogConfig {
+    /// Default level and per-module overrides
+    pub filter: Targets,
+    pub format: LogFormat,
+}
+
+#[derive(Clone, Copy)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+/// Delete behaviour of each relation, only set in the configuration file
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Relations {
+    /// Cards owned by a deleted user
+    pub user_cards: OnDelete,
+    /// Cards issued by a deleted bank
+    pub bank_cards: OnDelete,
+    /// Users whose home bank is deleted
+    pub bank_users: OnDelete,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnDelete {
+    /// Fail with `ENTITY_HAS_DEPENDENTS`
+    #[default]
+    Restrict,
+    /// Delete the dependent rows as well
+    Cascade,
+}
+
+impl Config {
+    /// Merges flags, environment and configuration file, in that order of precedence
+    pub fn load(args: ConfigArgs) -> Result<Self> {
+        let file = match &args.config {
+            Some(path) => read_file(path)?,
+            None if Path::new(DEFAULT_CONFIG).exists() => read_file(Path::new(DEFAULT_CONFIG))?,
+            None => FileConfig::default(),
+        };
+
+        let listen = args
+            .listen
+            .or(file.listen)
+            .unwrap_or_else(|| "127.0.0.1:3710".to_owned());
+        let listen = listen
+            .parse()
+            .with_context(|| format!("invalid listen address `{listen}`"))?;
+
+        let database_url = args
+            .database_url
+            .or(file.database_url)
+            .unwrap_or_else(|| "sqlite://bublik.db".to_owned());
+        if !database_url.starts_with("sqlite:") {
+            bail!("database url `{database_url}` must use the `sqlite:` scheme");
+        }
+
+        let pool_size = args.pool_size.or(file.pool_size).unwrap_or(5);
+        if pool_size == 0 {
+            bail!("pool size must be greater than zero");
+        }
+
+        let jwt_secret = args.jwt_secret.or(file.jwt_secret);
+        if jwt_secret.as_ref().is_some_and(|secret| secret.len() < 32) {
+            bail!("jwt secret must be at least 32 bytes long");
+        }
+
+        let level = args
+            .log_level
+            .or(file.log.level)
+            .unwrap_or_else(|| "info,sqlx=warn".to_owned());
+        let filter =
+            Targets::from_str(&level).with_context(|| format!("invalid log level `{level}`"))?;
+
+        let format = match args
+            .log_format
+            .or(file.log.format)
+            .as_deref()
+            .unwrap_or("text")
+        {
+            "text" => LogFormat::Text,
+            "json" => LogFormat::Json,
+            format => bail!("invalid log format `{format}`, expected `text` or `json`"),
+        };
+
+        Ok(Self {
+            listen,
+            database_url,
+            data_dir: args
+                .data_dir
+                .or(file.data_dir)
+                .unwrap_or_else(|| "data".into()),
+            pool_size,
+            swagger: args.swagger.or(file.swagger).unwrap_or(true),
+            metrics: args.metrics.or(file.metrics).unwrap_or(true),
+            admin: args.admin.or(file.admin).unwrap_or(false),
+            jwt_secret,
+            log: LogConfig { filter, format },
+            relations: file.relations,
+        })
+    }
+}
+
+fn read_file(path: &Path) -> Result<FileConfig> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("read config file {}", path.display()))?;
+    toml::from_str(&content).with_context(|| format!("parse config file {}", path.display()))
+}
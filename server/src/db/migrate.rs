@@ -0,0 +1,114 @@
+use anyhow::{bail, Context, Result};
+use sqlx::{
+    migrate::{Migrate, Migrator},
+    query_as, Pool, Sqlite,
+};
+use tracing::info;
+
+use crate::config::{Config, MigrateCommand};
+
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Applies pending migrations, refusing databases migrated by a newer binary
+pub async fn run(db: &Pool<Sqlite>) -> Result<()> {
+    let applied = applied_versions(db).await?;
+    if let Some(version) = applied
+        .iter()
+        .find(|version| MIGRATOR.iter().all(|m| m.version != **version))
+    {
+        bail!(
+            "database has migration {} applied which is unknown to this binary (latest {}), upgrade the server",
+            version,
+            latest_version()
+        );
+    }
+    MIGRATOR.run(db).await.context("run migrations")?;
+    info!("Database schema at version {}", latest_version());
+    Ok(())
+}
+
+/// Versions known to this binary but not applied yet, without touching the schema
+pub async fn pending(db: &Pool<Sqlite>) -> Result<Vec<i64>> {
+    let applied = query_as::<_, (i64,)>("SELECT version FROM _sqlx_migrations WHERE success = 1")
+        .fetch_all(db)
+        .await
+        .context("list applied migrations")?;
+    Ok(MIGRATOR
+        .iter()
+        .map(|m| m.version)
+        .filter(|version| applied.iter().all(|(applied,)| applied != version))
+        .collect())
+}
+
+pub async fn command(config: &Config, command: MigrateCommand) -> Result<()> {
+    match command {
+        MigrateCommand::Run => run(&super::connect(config).await?).await,
+        MigrateCommand::Status => {
+            let installed = match super::open_read_only(config).await? {
+                None => {
+                    println!("no database");
+                    Vec::new()
+                }
+                Some(db) if !has_migrations_table(&db).await? => {
+                    println!("no migrations table");
+                    Vec::new()
+                }
+                Some(db) => query_as::<_, (i64, String)>(
+                    "SELECT version, installed_on FROM _sqlx_migrations ORDER BY version",
+                )
+                .fetch_all(&db)
+                .await
+                .context("list migrations")?,
+            };
+            for migration in MIGRATOR.iter() {
+                let state = installed
+                    .iter()
+                    .find(|(version, _)| *version == migration.version)
+                    .map_or("pending".to_owned(), |(_, on)| format!("applied {on}"));
+                println!(
+                    "{:>4} {:<32} {}",
+                    migration.version, migration.description, state
+                );
+            }
+            for (version, on) in installed
+                .iter()
+                .filter(|(version, _)| MIGRATOR.iter().all(|m| m.version != *version))
+            {
+                println!(
+                    "{version:>4} {:<32} applied {on}, unknown to this binary",
+                    "?"
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Whether migrations ever ran, checked without creating their table
+async fn has_migrations_table(db: &Pool<Sqlite>) -> Result<bool> {
+    let (count,) = query_as::<_, (i64,)>(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'",
+    )
+    .fetch_one(db)
+    .await
+    .context("look up migrations table")?;
+    Ok(count > 0)
+}
+
+async fn applied_versions(db: &Pool<Sqlite>) -> Result<Vec<i64>> {
+    let mut conn = db.acquire().await.context("acquire connection")?;
+    conn.ensure_migrations_table()
+        .await
+        .context("create migrations table")?;
+    Ok(conn
+        .list_applied_migrations()
+        .await
+        .context("list applied migrations")?
+        .into_iter()
+        .map(|m| m.version)
+        .collect())
+}
+
+fn latest_version() -> i64 {
+    MIGRATOR.iter().map(|m| m.version).max().unwrap_or_default()
+}
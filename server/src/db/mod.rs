@@ -0,0 +1,120 @@
+use anyhow::{Context, Result};
+use sqlx::{
+    migrate::MigrateDatabase,
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    Pool, Sqlite,
+};
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tracing::info;
+
+use crate::{config::Config, models::seed::SeedMode};
+
+pub mod migrate;
+pub mod seed;
+
+pub async fn connect(config: &Config) -> Result<Pool<Sqlite>> {
+    let url = config.database_url.as_str();
+    if !Sqlite::database_exists(url).await.unwrap_or(false) {
+        info!("Creating databse {}", url);
+        Sqlite::create_database(url)
+            .await
+            .context("created database")?;
+    }
+    let db = SqlitePoolOptions::new()
+        .max_connections(config.pool_size)
+        .connect(url)
+        .await
+        .with_context(|| format!("connect to {url}"))?;
+    Ok(db)
+}
+
+/// Connects to an existing database without creating or writing it, `None` when there is none
+pub async fn open_read_only(config: &Config) -> Result<Option<Pool<Sqlite>>> {
+    let url = config.database_url.as_str();
+    if !Sqlite::database_exists(url).await.unwrap_or(false) {
+        return Ok(None);
+    }
+    let options = SqliteConnectOptions::from_str(url)
+        .with_context(|| format!("parse {url}"))?
+        .read_only(true);
+    let db = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await
+        .with_context(|| format!("connect to {url}"))?;
+    Ok(Some(db))
+}
+
+/// Seeds in progress, reported by the readiness probe. Startup seeding counts as running
+/// until [`prepare_database`] finishes it
+#[derive(Clone)]
+pub struct SeedStatus(Arc<AtomicUsize>);
+
+impl Default for SeedStatus {
+    fn default() -> Self {
+        Self(Arc::new(AtomicUsize::new(1)))
+    }
+}
+
+impl SeedStatus {
+    pub fn is_seeding(&self) -> bool {
+        self.0.load(Ordering::Acquire) > 0
+    }
+
+    /// Marks a seed as running until the guard is dropped
+    pub fn running(&self) -> SeedRunning {
+        self.0.fetch_add(1, Ordering::AcqRel);
+        SeedRunning(self.clone())
+    }
+
+    fn finish(&self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Seed in progress, see [`SeedStatus::running`]
+pub struct SeedRunning(SeedStatus);
+
+impl Drop for SeedRunning {
+    fn drop(&mut self) {
+        self.0.finish();
+    }
+}
+
+/// Applies migrations and loads fixtures into empty tables, then finishes startup seeding in
+/// `status`
+pub async fn prepare_database(
+    db: &Pool<Sqlite>,
+    config: &Config,
+    status: &SeedStatus,
+) -> Result<()> {
+    migrate::run(db).await?;
+    seed::seed(db, &config.data_dir, SeedMode::Missing).await?;
+    status.finish();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seed_status_tracks_startup_and_requested_seeds() {
+        let status = SeedStatus::default();
+        assert!(status.is_seeding());
+        status.finish();
+        assert!(!status.is_seeding());
+        let first = status.running();
+        let second = status.running();
+        drop(first);
+        assert!(status.is_seeding());
+        drop(second);
+        assert!(!status.is_seeding());
+    }
+}
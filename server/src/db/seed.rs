@@ -0,0 +1,406 @@
+use anyhow::{bail, Context, Result};
+use serde::de::DeserializeOwned;
+use sqlx::{error::ErrorKind, query, query_as, Pool, Sqlite, SqliteConnection};
+use std::{io, path::Path};
+use tokio::fs;
+use tracing::{debug, info, warn};
+
+use crate::{
+    api::{
+        validation_error::{ValidationError, ValidationErrors},
+        validation_rules::exists,
+    },
+    models::{
+        bank::{Bank, CreateBank},
+        card::{Card, CreateCard},
+        seed::{SeedMode, SeedReport, SeedTable},
+        user::{CreateUser, User},
+    },
+};
+
+/// Loads `banks.json`, `users.json` and `cards.json` from `dir` within one transaction
+pub async fn seed(db: &Pool<Sqlite>, dir: &Path, mode: SeedMode) -> Result<SeedReport> {
+    let mut tx = db.begin().await.context("begin seed")?;
+    if mode == SeedMode::Reset {
+        // Clearing users cascades to their API keys, which are put back once users are loaded
+        query("CREATE TEMP TABLE seed_api_keys AS SELECT * FROM api_keys")
+            .execute(&mut *tx)
+            .await
+            .context("save api keys")?;
+        for table in ["cards", "users", "banks"] {
+            query(&format!("DELETE FROM {table}"))
+                .execute(&mut *tx)
+                .await
+                .with_context(|| format!("clear {table}"))?;
+        }
+    }
+    let report = SeedReport {
+        banks: seed_banks(&mut tx, dir, mode).await?,
+        users: seed_users(&mut tx, dir, mode).await?,
+        cards: seed_cards(&mut tx, dir, mode).await?,
+    };
+    if mode == SeedMode::Reset {
+        restore_api_keys(&mut tx).await?;
+    }
+    tx.commit().await.context("commit seed")?;
+
+    for table in [&report.banks, &report.users, &report.cards] {
+        if table.loaded > 0 {
+            info!("Loaded {} {}", table.loaded, table.table);
+        }
+        for rejection in &table.rejected {
+            warn!(
+                "Rejected {} {}: {} {}",
+                table.table,
+                rejection.id,
+                rejection.code,
+                serde_json::to_string(&rejection.parameters).unwrap_or_default()
+            );
+        }
+    }
+    Ok(report)
+}
+
+/// Runs pending migrations and seeds the database from the command line
+pub async fn command(db: &Pool<Sqlite>, dir: &Path, mode: SeedMode) -> Result<()> {
+    if !dir.is_dir() {
+        bail!("seed directory {} does not exist", dir.display());
+    }
+    super::migrate::run(db).await?;
+    let report = seed(db, dir, mode).await?;
+    for table in [&report.banks, &report.users, &report.cards] {
+        let state = if table.skipped {
+            "skipped, table is not empty".to_owned()
+        } else if !table.found {
+            format!("skipped, no {}.json", table.table)
+        } else {
+            format!(
+                "loaded {}, rejected {}",
+                table.loaded,
+                table.rejected_rows()
+            )
+        };
+        println!("{:<6} {}", table.table, state);
+        for rejection in &table.rejected {
+            println!(
+                "{:>6} {} {} {}",
+                rejection.id,
+                rejection.field.as_deref().unwrap_or("-"),
+                rejection.code,
+                serde_json::to_string(&rejection.parameters).unwrap_or_default()
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn seed_banks(conn: &mut SqliteConnection, dir: &Path, mode: SeedMode) -> Result<SeedTable> {
+    let mut report = SeedTable::new("banks");
+    let Some(banks) = fixtures::<Bank>(conn, dir, mode, &mut report).await? else {
+        return Ok(report);
+    };
+    let sql = insert(
+        "banks",
+        &["country", "city", "zipcode", "street", "buildingNumber"],
+        mode,
+    );
+    for bank in banks {
+        let mut data = CreateBank {
+            country: bank.country,
+            city: bank.city,
+            zipcode: bank.zipcode,
+            street: bank.street,
+            building_number: bank.building_number,
+        };
+        if let Err(err) = data.validate() {
+            for error in validation_errors(err) {
+                report.reject(bank.id, &error);
+            }
+            continue;
+        }
+        query(&sql)
+            .bind(bank.id)
+            .bind(data.country)
+            .bind(data.city)
+            .bind(data.zipcode)
+            .bind(data.street)
+            .bind(data.building_number)
+            .execute(&mut *conn)
+            .await
+            .context("insert bank")?;
+        report.loaded += 1;
+    }
+    Ok(report)
+}
+
+async fn seed_users(conn: &mut SqliteConnection, dir: &Path, mode: SeedMode) -> Result<SeedTable> {
+    let mut report = SeedTable::new("users");
+    let Some(users) = fixtures::<User>(conn, dir, mode, &mut report).await? else {
+        return Ok(report);
+    };
+    let sql = insert(
+        "users",
+        &[
+            "firstName",
+            "lastName",
+            "email",
+            "phone",
+            "birthday",
+            "userType",
+            "bankId",
+        ],
+        mode,
+    );
+    for user in users {
+        let mut data = CreateUser {
+            first_name: user.first_name,
+            last_name: user.last_name,
+            email: user.email,
+            phone: user.phone,
+            birthday: user.birthday,
+            user_type: user.user_type,
+            bank_id: user.bank_id,
+        };
+        if let Err(err) = data.validate() {
+            for error in validation_errors(err) {
+                report.reject(user.id, &error);
+            }
+            continue;
+        }
+        if let Some(bank_id) = data.bank_id {
+            if !exists(&mut *conn, "banks", bank_id).await? {
+                report.reject(user.id, &ValidationError::ReferenceNotExists("Bank"));
+                continue;
+            }
+        }
+        let result = query(&sql)
+            .bind(user.id)
+            .bind(&data.first_name)
+            .bind(&data.last_name)
+            .bind(&data.email)
+            .bind(&data.phone)
+            .bind(data.birthday)
+            .bind(data.user_type as u32)
+            .bind(data.bank_id)
+            .execute(&mut *conn)
+            .await;
+        match result {
+            Ok(_) => report.loaded += 1,
+            Err(err) if is_unique_violation(&err) => report.reject(
+                user.id,
+                &ValidationError::UserEmailAlreadyExists(data.email),
+            ),
+            Err(err) => return Err(err).context("insert user"),
+        }
+    }
+    Ok(report)
+}
+
+async fn seed_cards(conn: &mut SqliteConnection, dir: &Path, mode: SeedMode) -> Result<SeedTable> {
+    let mut report = SeedTable::new("cards");
+    let Some(cards) = fixtures::<Card>(conn, dir, mode, &mut report).await? else {
+        return Ok(report);
+    };
+    let sql = insert(
+        "cards",
+        &[
+            "cardType",
+            "number",
+            "expiration",
+            "owner",
+            "ownerId",
+            "bankId",
+        ],
+        mode,
+    );
+    for card in cards {
+        let mut data = CreateCard {
+            card_type: card.card_type,
+            number: card.number,
+            expiration: card.expiration,
+            owner: card.owner,
+            owner_id: card.owner_id,
+            bank_id: card.bank_id,
+        };
+        if let Err(err) = data.validate() {
+            for error in validation_errors(err) {
+                report.reject(card.id, &error);
+            }
+            continue;
+        }
+        if let Some(owner_id) = data.owner_id {
+            if !exists(&mut *conn, "users", owner_id).await? {
+                report.reject(card.id, &ValidationError::ReferenceNotExists("User"));
+                continue;
+            }
+        }
+        if let Some(bank_id) = data.bank_id {
+            if !exists(&mut *conn, "banks", bank_id).await? {
+                report.reject(card.id, &ValidationError::ReferenceNotExists("Bank"));
+                continue;
+            }
+        }
+        let result = query(&sql)
+            .bind(card.id)
+            .bind(data.card_type as u32)
+            .bind(&data.number)
+            .bind(&data.expiration)
+            .bind(&data.owner)
+            .bind(data.owner_id)
+            .bind(data.bank_id)
+            .execute(&mut *conn)
+            .await;
+        match result {
+            Ok(_) => report.loaded += 1,
+            Err(err) if is_unique_violation(&err) => report.reject(
+                card.id,
+                &ValidationError::CardNumberAlreadyExists(data.number),
+            ),
+            Err(err) => return Err(err).context("insert card"),
+        }
+    }
+    Ok(report)
+}
+
+/// Restores the API keys saved before a reset, dropping those of users no longer seeded
+async fn restore_api_keys(conn: &mut SqliteConnection) -> Result<()> {
+    let (saved,) = query_as::<_, (u32,)>("SELECT COUNT(*) FROM seed_api_keys")
+        .fetch_one(&mut *conn)
+        .await
+        .context("count saved api keys")?;
+    let restored = query(
+        "INSERT INTO api_keys SELECT * FROM seed_api_keys WHERE userId IN (SELECT id FROM users)",
+    )
+    .execute(&mut *conn)
+    .await
+    .context("restore api keys")?
+    .rows_affected();
+    query("DROP TABLE seed_api_keys")
+        .execute(&mut *conn)
+        .await
+        .context("drop saved api keys")?;
+    let dropped = u64::from(saved) - restored;
+    if dropped > 0 {
+        warn!("Dropped {dropped} API keys of users no longer seeded");
+    }
+    Ok(())
+}
+
+/// Reads `<table>.json`, `None` when there is nothing to load
+async fn fixtures<T: DeserializeOwned>(
+    conn: &mut SqliteConnection,
+    dir: &Path,
+    mode: SeedMode,
+    report: &mut SeedTable,
+) -> Result<Option<Vec<T>>> {
+    let table = report.table.clone();
+    if mode == SeedMode::Missing {
+        let (count,) = query_as::<_, (u32,)>(&format!("SELECT COUNT(*) FROM {table}"))
+            .fetch_one(&mut *conn)
+            .await
+            .with_context(|| format!("count {table}"))?;
+        if count > 0 {
+            report.skipped = true;
+            return Ok(None);
+        }
+    }
+    let path = dir.join(format!("{table}.json"));
+    let bytes = match fs::read(&path).await {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            debug!("No {} fixtures in {}", table, dir.display());
+            return Ok(None);
+        }
+        Err(err) => return Err(err).with_context(|| format!("read {}", path.display())),
+    };
+    report.found = true;
+    let fixtures: Vec<T> =
+        serde_json::from_slice(&bytes).with_context(|| format!("parse {}", path.display()))?;
+    debug!("Read {} {} fixtures", fixtures.len(), table);
+    Ok(Some(fixtures))
+}
+
+/// Insert statement for `id` and `columns`, overwriting existing rows in upsert mode
+fn insert(table: &str, columns: &[&str], mode: SeedMode) -> String {
+    let mut sql = format!(
+        "INSERT INTO {table} (id, {}) VALUES (?{})",
+        columns.join(", "),
+        ", ?".repeat(columns.len())
+    );
+    if mode == SeedMode::Upsert {
+        let updates: Vec<_> = columns
+            .iter()
+            .map(|column| format!("{column} = excluded.{column}"))
+            .collect();
+        sql.push_str(&format!(
+            " ON CONFLICT (id) DO UPDATE SET {}",
+            updates.join(", ")
+        ));
+    }
+    sql
+}
+
+fn is_unique_violation(err: &sqlx::Error) -> bool {
+    err.as_database_error()
+        .is_some_and(|err| err.kind() == ErrorKind::UniqueViolation)
+}
+
+fn validation_errors(err: poem::Error) -> Vec<ValidationError> {
+    match err.downcast::<ValidationErrors>() {
+        Ok(errors) => errors.0,
+        Err(err) => vec![err
+            .downcast::<ValidationError>()
+            .unwrap_or(ValidationError::Unknown)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+    use crate::db::migrate;
+
+    fn data_dir() -> &'static Path {
+        Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../data"))
+    }
+
+    #[tokio::test]
+    async fn reset_keeps_api_keys_of_seeded_users() {
+        // A single connection keeps the in-memory database alive
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        migrate::run(&db).await.unwrap();
+        seed(&db, data_dir(), SeedMode::Reset).await.unwrap();
+        query(
+            "INSERT INTO users (id, firstName, lastName, email, phone, birthday, userType)
+                VALUES (1000, 'Ola', 'Nowak', 'ola@bublik.pl', '+48100200300', '1990-01-01', 3)",
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        query(
+            "INSERT INTO api_keys (id, userId, name, hash) VALUES
+                (1, 1, 'fixture', 'fixture-hash'), (2, 1000, 'added', 'added-hash')",
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let report = seed(&db, data_dir(), SeedMode::Reset).await.unwrap();
+        assert_eq!(report.users.loaded, 100);
+        let keys = query_as::<_, (u32, u32, String, String)>(
+            "SELECT id, userId, name, hash FROM api_keys ORDER BY id",
+        )
+        .fetch_all(&db)
+        .await
+        .unwrap();
+        assert_eq!(
+            keys,
+            [(1, 1, "fixture".to_owned(), "fixture-hash".to_owned())]
+        );
+    }
+}
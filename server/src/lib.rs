@@ -0,0 +1,7 @@
+// `poem::Error` is the error type of every request path, boxing it would only move the cost
+#![allow(clippy::result_large_err)]
+
+pub mod api;
+pub mod config;
+pub mod db;
+pub mod models;
@@ -1,22 +1,53 @@
 use anyhow::{Context, Result};
+use bublik_server::{
+    api,
+    config::{Cli, Command, Config, LogConfig, LogFormat},
+    db,
+};
+use clap::Parser;
 use poem::{listener::TcpListener, Server};
-use tracing::{metadata::LevelFilter, trace};
-
-mod api;
-mod db;
-mod models;
+use tracing::trace;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt()
-        .with_max_level(LevelFilter::TRACE)
-        .with_target(false)
-        .init();
+    let cli = Cli::parse();
+    let config = Config::load(cli.config).context("configuration")?;
+    init_logging(&config.log);
     trace!("Hi!");
 
-    let db = db::prepare_database().await?;
-    Server::new(TcpListener::bind("51.75.55.235:3710"))
-        .run(api::routes(&db))
+    // Migrations connect on their own, `migrate status` must not create the database
+    let command = match cli.command {
+        Some(Command::Migrate { command }) => return db::migrate::command(&config, command).await,
+        command => command,
+    };
+    let db = db::connect(&config).await?;
+    match command {
+        Some(Command::Seed { mode, dir }) => {
+            return db::seed::command(&db, dir.as_deref().unwrap_or(&config.data_dir), mode).await
+        }
+        Some(Command::Auth { command }) => {
+            db::migrate::run(&db).await?;
+            return api::auth::command(&db, config.jwt_secret.as_deref(), command).await;
+        }
+        Some(Command::Migrate { .. }) | None => {}
+    }
+
+    let seed = db::SeedStatus::default();
+    db::prepare_database(&db, &config, &seed).await?;
+    Server::new(TcpListener::bind(config.listen))
+        .run(api::routes(&db, &config, &seed))
         .await
         .context("server")
 }
+
+fn init_logging(log: &LogConfig) {
+    let registry = tracing_subscriber::registry().with(log.filter.clone());
+    // Stdout is reserved for command output such as printed API keys
+    let layer = tracing_subscriber::fmt::layer().with_writer(std::io::stderr);
+    match log.format {
+        LogFormat::Text => registry.with(layer.with_target(false)).init(),
+        // Targets such as `access` tell JSON consumers which events they got
+        LogFormat::Json => registry.with(layer.json()).init(),
+    }
+}
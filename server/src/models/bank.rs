@@ -3,6 +3,12 @@ use poem_openapi::Object;
 use serde::Deserialize;
 use sqlx::FromRow;
 
+use super::browse::{Conditions, Filter, Key, Keyset};
+use crate::api::{
+    validation_error::ValidationError,
+    validation_rules::{referenced, ValidationContext},
+};
+
 #[derive(Object, Deserialize, FromRow)]
 #[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
 #[serde(rename_all = "camelCase")]
@@ -28,6 +34,63 @@ pub struct CreateBank {
     pub building_number: String,
 }
 
+#[derive(Object, Validation)]
+#[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
+#[val(trim, length = "field_length")]
+pub struct PatchBank {
+    pub country: Option<String>,
+    pub city: Option<String>,
+    pub zipcode: Option<String>,
+    pub street: Option<String>,
+    #[val(length = "building_field_length")]
+    pub building_number: Option<String>,
+}
+
+/// Sortable by `id`, `country`, `city`, `zipcode`, `street` and `buildingNumber`
+#[derive(Object)]
+#[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
+pub struct BankFilter {
+    pub country: Option<String>,
+    pub city: Option<String>,
+}
+
+impl Filter for BankFilter {
+    const SORT_FIELDS: &'static [&'static str] = &[
+        "id",
+        "country",
+        "city",
+        "zipcode",
+        "street",
+        "buildingNumber",
+    ];
+
+    fn push_conditions(&self, conditions: &mut Conditions<'_, '_>) {
+        if let Some(country) = &self.country {
+            conditions.push("country = ", country.clone());
+        }
+        if let Some(city) = &self.city {
+            conditions.push("city = ", city.clone());
+        }
+    }
+}
+
+impl Keyset for Bank {
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn key(&self, field: &str) -> Key {
+        match field {
+            "country" => self.country.clone().into(),
+            "city" => self.city.clone().into(),
+            "zipcode" => self.zipcode.clone().into(),
+            "street" => self.street.clone().into(),
+            "buildingNumber" => self.building_number.clone().into(),
+            _ => self.id.into(),
+        }
+    }
+}
+
 fn field_length() -> (usize, usize) {
     (3, 64)
 }
@@ -35,3 +98,43 @@ fn field_length() -> (usize, usize) {
 fn building_field_length() -> (usize, usize) {
     (1, 32)
 }
+
+/// Check of fields referencing a bank
+pub async fn bank_exists(
+    id: &u32,
+    ctx: &ValidationContext<'_>,
+) -> anyhow::Result<Option<ValidationError>> {
+    referenced(ctx, "banks", "Bank", *id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::validation_error::ValidationErrors;
+
+    #[test]
+    fn aggregates_field_errors_with_camel_case_names() {
+        let mut bank = CreateBank {
+            country: " Poland ".to_owned(),
+            city: "Ab".to_owned(),
+            zipcode: "00-001".to_owned(),
+            street: "Długa".to_owned(),
+            building_number: "1".repeat(33),
+        };
+        let err = bank.validate().unwrap_err();
+        let errors = err.downcast_ref::<ValidationErrors>().unwrap();
+        let failed: Vec<_> = errors
+            .0
+            .iter()
+            .map(|error| (error.field(), error.to_string()))
+            .collect();
+        assert_eq!(
+            failed,
+            [
+                (Some("city".to_owned()), "MIN_LENGTH".to_owned()),
+                (Some("buildingNumber".to_owned()), "MAX_LENGTH".to_owned()),
+            ]
+        );
+        assert_eq!(bank.country, "Poland");
+    }
+}
@@ -1,23 +1,389 @@
-use poem_openapi::Object;
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use poem_openapi::{
+    types::{ParseFromJSON, ToJSON},
+    Enum, Object,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{sqlite::SqliteRow, Encode, FromRow, Pool, QueryBuilder, Sqlite, Type};
+
+use crate::api::{
+    api_error::{ErrorCode, ErrorCodes},
+    validation_error::{codes, ValidationError},
+};
 
 #[derive(Object)]
 #[oai(rename_all = "camelCase")]
-pub struct Browse {
+pub struct Browse<F: ParseFromJSON + ToJSON> {
+    /// Ignored when `cursor` is set
     page_number: u32,
     #[oai(default = "default_count")]
     count: u32,
+    filter: Option<F>,
+    /// One of the sortable fields listed in the filter description
+    sort_by: Option<String>,
+    #[oai(default)]
+    sort_direction: SortDirection,
+    /// `nextCursor` of the previous page, requires the same sorting
+    cursor: Option<String>,
+}
+
+/// Single page of browse results
+#[derive(Object)]
+#[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
+pub struct Page<T: ParseFromJSON + ToJSON> {
+    items: Vec<T>,
+    page_number: u32,
+    count: u32,
+    total_items: u32,
+    total_pages: u32,
+    has_next: bool,
+    /// Resumes browsing right after the last item of this page
+    next_cursor: Option<String>,
+}
+
+#[derive(Enum, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[oai(rename_all = "lowercase")]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Entity specific browse filter
+pub trait Filter {
+    /// Columns accepted by `sortBy`
+    const SORT_FIELDS: &'static [&'static str];
+
+    fn push_conditions(&self, conditions: &mut Conditions<'_, '_>);
+}
+
+/// Position of an entity in a sorted listing
+pub trait Keyset {
+    fn id(&self) -> u32;
+
+    /// Value of a sortable field
+    fn key(&self, field: &str) -> Key;
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Key {
+    Integer(i64),
+    Text(String),
+}
+
+/// Decoded `cursor`, bound to the sorting it was created with
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    sort_by: Option<String>,
+    direction: SortDirection,
+    key: Option<Key>,
+    id: u32,
+}
+
+/// `WHERE` clause builder joining conditions with `AND`
+pub struct Conditions<'q, 'args> {
+    query: &'q mut QueryBuilder<'args, Sqlite>,
+    empty: bool,
+}
+
+impl<F: ParseFromJSON + ToJSON> ErrorCodes for Browse<F> {
+    fn codes() -> Vec<ErrorCode> {
+        <(codes::UnknownSortField, codes::InvalidCursor)>::codes()
+    }
+}
+
+impl<F: ParseFromJSON + ToJSON + Filter> Browse<F> {
+    pub fn validate(&self) -> poem::Result<()> {
+        match &self.sort_by {
+            Some(sort_by) if !F::SORT_FIELDS.contains(&sort_by.as_str()) => {
+                Err(ValidationError::UnknownSortField(sort_by.clone()).into())
+            }
+            _ => self.cursor().map(|_| ()),
+        }
+    }
+
+    /// Fetches the requested page and the filtered total within one transaction
+    pub async fn fetch<T>(&self, db: &Pool<Sqlite>, table: &str) -> poem::Result<Page<T>>
+    where
+        T: for<'r> FromRow<'r, SqliteRow> + ParseFromJSON + ToJSON + Keyset + Unpin,
+    {
+        let cursor = self.cursor()?;
+        let mut tx = db.begin().await.context("begin browse")?;
+
+        let mut query = QueryBuilder::new(format!("SELECT COUNT(*) FROM {table}"));
+        let mut conditions = Conditions::new(&mut query);
+        self.push_filter(&mut conditions);
+        let total_items = query
+            .build_query_as::<(u32,)>()
+            .fetch_one(&mut *tx)
+            .await
+            .with_context(|| format!("count {table}"))?
+            .0;
+
+        let mut query = QueryBuilder::new(format!("SELECT * FROM {table}"));
+        let mut conditions = Conditions::new(&mut query);
+        self.push_filter(&mut conditions);
+        if let Some(cursor) = cursor {
+            conditions.push_after(
+                self.sort_field(),
+                cursor.key,
+                cursor.id,
+                self.sort_direction,
+            );
+        }
+        self.push_page(&mut query);
+        let mut items = query
+            .build_query_as::<T>()
+            .fetch_all(&mut *tx)
+            .await
+            .with_context(|| format!("browse {table}"))?;
+
+        tx.commit().await.context("commit browse")?;
+        let has_next = items.len() > self.count as usize;
+        items.truncate(self.count as usize);
+        Ok(self.page(items, total_items, has_next))
+    }
+
+    fn cursor(&self) -> poem::Result<Option<Cursor>> {
+        let Some(cursor) = &self.cursor else {
+            return Ok(None);
+        };
+        URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|cursor| serde_json::from_slice::<Cursor>(&cursor).ok())
+            .filter(|cursor| {
+                cursor.sort_by.as_deref() == self.sort_field()
+                    && cursor.direction == self.sort_direction
+                    && cursor.key.is_some() == self.sort_field().is_some()
+            })
+            .map(Some)
+            .ok_or_else(|| ValidationError::InvalidCursor(cursor.clone()).into())
+    }
+
+    /// Sort column other than the implicit `id` tiebreaker
+    fn sort_field(&self) -> Option<&str> {
+        self.sort_by.as_deref().filter(|field| *field != "id")
+    }
+
+    fn push_filter(&self, conditions: &mut Conditions<'_, '_>) {
+        if let Some(filter) = &self.filter {
+            filter.push_conditions(conditions);
+        }
+    }
+
+    /// Orders and limits the query, fetching one extra row to detect the next page
+    fn push_page(&self, query: &mut QueryBuilder<'_, Sqlite>) {
+        let direction = self.sort_direction.sql();
+        match self.sort_field() {
+            Some(field) => query.push(format!(" ORDER BY {field} {direction}, id {direction}")),
+            None => query.push(format!(" ORDER BY id {direction}")),
+        };
+        let skip = match self.cursor {
+            Some(_) => 0,
+            None => self.page_number.saturating_mul(self.count),
+        };
+        query
+            .push(" LIMIT ")
+            .push_bind(skip)
+            .push(", ")
+            .push_bind(self.count.saturating_add(1));
+    }
+
+    fn page<T>(&self, items: Vec<T>, total_items: u32, has_next: bool) -> Page<T>
+    where
+        T: ParseFromJSON + ToJSON + Keyset,
+    {
+        let total_pages = match self.count {
+            0 => 0,
+            count => total_items.div_ceil(count),
+        };
+        let next_cursor = items.last().filter(|_| has_next).map(|last| {
+            let cursor = Cursor {
+                sort_by: self.sort_field().map(str::to_owned),
+                direction: self.sort_direction,
+                key: self.sort_field().map(|field| last.key(field)),
+                id: last.id(),
+            };
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&cursor).unwrap_or_default())
+        });
+        Page {
+            items,
+            page_number: self.page_number,
+            count: self.count,
+            total_items,
+            total_pages,
+            has_next,
+            next_cursor,
+        }
+    }
+}
+
+impl SortDirection {
+    fn sql(self) -> &'static str {
+        match self {
+            Self::Asc => "ASC",
+            Self::Desc => "DESC",
+        }
+    }
+}
+
+impl From<u32> for Key {
+    fn from(value: u32) -> Self {
+        Self::Integer(value.into())
+    }
+}
+
+impl From<String> for Key {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
 }
 
-impl Browse {
-    pub fn page_number(&self) -> u32 {
-        self.page_number
+impl<'q, 'args> Conditions<'q, 'args> {
+    pub fn new(query: &'q mut QueryBuilder<'args, Sqlite>) -> Self {
+        Self { query, empty: true }
+    }
+
+    /// Pushes `condition` followed by a bound `value`, e.g. `("userType = ", 1)`
+    pub fn push<T>(&mut self, condition: &str, value: T) -> &mut Self
+    where
+        T: 'args + Encode<'args, Sqlite> + Send + Type<Sqlite>,
+    {
+        self.separator().push(condition).push_bind(value);
+        self
+    }
+
+    /// Case insensitive prefix match on `column`
+    pub fn push_prefix(&mut self, column: &str, prefix: &str) -> &mut Self {
+        let pattern = prefix
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        self.push(&format!("{column} LIKE "), format!("{pattern}%"));
+        self.query.push(" ESCAPE '\\'");
+        self
     }
 
-    pub fn count(&self) -> u32 {
-        self.count
+    /// Keyset condition selecting rows past `(key, id)` in the given direction
+    fn push_after(
+        &mut self,
+        column: Option<&str>,
+        key: Option<Key>,
+        id: u32,
+        direction: SortDirection,
+    ) -> &mut Self {
+        let operator = match direction {
+            SortDirection::Asc => ">",
+            SortDirection::Desc => "<",
+        };
+        let query = self.separator();
+        match (column, key) {
+            (Some(column), Some(key)) => {
+                query.push(format!("({column}, id) {operator} ("));
+                match key {
+                    Key::Integer(key) => query.push_bind(key),
+                    Key::Text(key) => query.push_bind(key),
+                };
+                query.push(", ").push_bind(id).push(")");
+            }
+            _ => {
+                query.push(format!("id {operator} ")).push_bind(id);
+            }
+        }
+        self
+    }
+
+    fn separator(&mut self) -> &mut QueryBuilder<'args, Sqlite> {
+        let separator = if self.empty { " WHERE " } else { " AND " };
+        self.empty = false;
+        self.query.push(separator)
     }
 }
 
 fn default_count() -> u32 {
     10
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::bank::{Bank, BankFilter};
+
+    fn browse(sort_by: &str, sort_direction: SortDirection) -> Browse<BankFilter> {
+        Browse {
+            page_number: 0,
+            count: 1,
+            filter: None,
+            sort_by: Some(sort_by.to_owned()),
+            sort_direction,
+            cursor: None,
+        }
+    }
+
+    fn next_cursor(browse: &Browse<BankFilter>) -> String {
+        let bank = Bank {
+            id: 7,
+            country: "Poland".to_owned(),
+            city: "Kraków".to_owned(),
+            zipcode: "30-001".to_owned(),
+            street: "Długa".to_owned(),
+            building_number: "1".to_owned(),
+        };
+        browse.page(vec![bank], 2, true).next_cursor.unwrap()
+    }
+
+    fn is_invalid_cursor(result: poem::Result<Option<Cursor>>) -> bool {
+        matches!(
+            result
+                .map(|_| ())
+                .unwrap_err()
+                .downcast_ref::<ValidationError>(),
+            Some(ValidationError::InvalidCursor(_))
+        )
+    }
+
+    #[test]
+    fn cursor_round_trip() {
+        let mut browse = browse("city", SortDirection::Desc);
+        browse.cursor = Some(next_cursor(&browse));
+        let cursor = browse.cursor().unwrap().unwrap();
+        assert_eq!(cursor.id, 7);
+        assert!(matches!(cursor.key, Some(Key::Text(city)) if city == "Kraków"));
+    }
+
+    #[test]
+    fn cursor_without_sort_field() {
+        let mut browse = browse("id", SortDirection::Asc);
+        browse.cursor = Some(next_cursor(&browse));
+        let cursor = browse.cursor().unwrap().unwrap();
+        assert_eq!(cursor.id, 7);
+        assert!(cursor.key.is_none());
+    }
+
+    #[test]
+    fn tampered_cursor() {
+        let mut browse = browse("city", SortDirection::Desc);
+        let cursor = next_cursor(&browse);
+        browse.cursor = Some(format!("{cursor}!"));
+        assert!(is_invalid_cursor(browse.cursor()));
+        browse.cursor = Some(URL_SAFE_NO_PAD.encode(r#"{"id":"x"}"#));
+        assert!(is_invalid_cursor(browse.cursor()));
+    }
+
+    #[test]
+    fn cursor_bound_to_sorting() {
+        let cursor = next_cursor(&browse("city", SortDirection::Desc));
+        for (sort_by, direction) in [
+            ("city", SortDirection::Asc),
+            ("country", SortDirection::Desc),
+            ("id", SortDirection::Desc),
+        ] {
+            let mut browse = browse(sort_by, direction);
+            browse.cursor = Some(cursor.clone());
+            assert!(is_invalid_cursor(browse.cursor()), "{sort_by}");
+        }
+    }
+}
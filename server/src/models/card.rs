@@ -1,9 +1,20 @@
 use bublik_macros::Validation;
+use chrono::Datelike;
 use int_enum::IntEnum;
 use poem_openapi::{Enum, Object};
 use serde::Deserialize;
 use sqlx::FromRow;
 
+use super::{
+    bank::bank_exists,
+    browse::{Conditions, Filter, Key, Keyset},
+    user::user_exists,
+};
+use crate::api::{
+    validation_error::ValidationError,
+    validation_rules::{taken, today, ValidationContext},
+};
+
 #[derive(Object, Deserialize, FromRow)]
 #[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
 #[serde(rename_all = "camelCase")]
@@ -16,6 +27,8 @@ pub struct Card {
     pub number: String,
     pub expiration: String,
     pub owner: String,
+    pub owner_id: Option<u32>,
+    pub bank_id: Option<u32>,
 }
 
 #[repr(u32)]
@@ -45,13 +58,118 @@ impl From<u32> for CardType {
 #[val(trim, length = "field_length")]
 pub struct CreateCard {
     pub card_type: CardType,
-    #[val(pattern = r"^[0-9]*$")]
+    #[val(luhn, check(path = "number_available", codes(CardNumberAlreadyExists)))]
     pub number: String,
-    #[val(pattern = r"^(0[1-9]|1[0-2])\/?([0-9]{4}|[0-9]{2})$")]
+    #[val(
+        pattern = r"^(0[1-9]|1[0-2])\/?([0-9]{4}|[0-9]{2})$",
+        rule(path = "not_expired", codes(CardExpired))
+    )]
     pub expiration: String,
     pub owner: String,
+    /// Owning user
+    #[val(check(path = "user_exists", codes(ReferenceNotExists)))]
+    pub owner_id: Option<u32>,
+    /// Issuing bank
+    #[val(check(path = "bank_exists", codes(ReferenceNotExists)))]
+    pub bank_id: Option<u32>,
+}
+
+#[derive(Object, Validation)]
+#[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
+#[val(trim, length = "field_length")]
+pub struct PatchCard {
+    pub card_type: Option<CardType>,
+    #[val(luhn, check(path = "number_available", codes(CardNumberAlreadyExists)))]
+    pub number: Option<String>,
+    #[val(
+        pattern = r"^(0[1-9]|1[0-2])\/?([0-9]{4}|[0-9]{2})$",
+        rule(path = "not_expired", codes(CardExpired))
+    )]
+    pub expiration: Option<String>,
+    pub owner: Option<String>,
+    #[val(check(path = "user_exists", codes(ReferenceNotExists)))]
+    pub owner_id: Option<u32>,
+    #[val(check(path = "bank_exists", codes(ReferenceNotExists)))]
+    pub bank_id: Option<u32>,
+}
+
+/// Sortable by `id`, `cardType`, `number`, `expiration` and `owner`
+#[derive(Object)]
+#[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
+pub struct CardFilter {
+    pub card_type: Option<CardType>,
+    /// Owner name prefix
+    pub owner: Option<String>,
+    pub owner_id: Option<u32>,
+    pub bank_id: Option<u32>,
+}
+
+impl Filter for CardFilter {
+    const SORT_FIELDS: &'static [&'static str] =
+        &["id", "cardType", "number", "expiration", "owner"];
+
+    fn push_conditions(&self, conditions: &mut Conditions<'_, '_>) {
+        if let Some(card_type) = self.card_type {
+            conditions.push("cardType = ", card_type as u32);
+        }
+        if let Some(owner) = &self.owner {
+            conditions.push_prefix("owner", owner);
+        }
+        if let Some(owner_id) = self.owner_id {
+            conditions.push("ownerId = ", owner_id);
+        }
+        if let Some(bank_id) = self.bank_id {
+            conditions.push("bankId = ", bank_id);
+        }
+    }
+}
+
+impl Keyset for Card {
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn key(&self, field: &str) -> Key {
+        match field {
+            "cardType" => (self.card_type as u32).into(),
+            "number" => self.number.clone().into(),
+            "expiration" => self.expiration.clone().into(),
+            "owner" => self.owner.clone().into(),
+            _ => self.id.into(),
+        }
+    }
 }
 
 fn field_length() -> (usize, usize) {
     (3, 64)
 }
+
+/// Cards stay valid until the end of their expiration month
+fn not_expired(expiration: &str) -> Result<(), ValidationError> {
+    let Some((month, year)) = month_year(expiration) else {
+        return Ok(());
+    };
+    let today = today();
+    if (year, month) < (today.year(), today.month()) {
+        Err(ValidationError::CardExpired(expiration.to_owned()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Splits `MM/YY`, `MMYY`, `MM/YYYY` or `MMYYYY`, malformed values are left to the pattern rule
+fn month_year(expiration: &str) -> Option<(u32, i32)> {
+    let month = expiration.get(..2)?.parse().ok()?;
+    let year = expiration.get(2..)?.trim_start_matches('/');
+    let century = if year.len() == 2 { 2000 } else { 0 };
+    Some((month, century + year.parse::<i32>().ok()?))
+}
+
+async fn number_available(
+    number: &str,
+    ctx: &ValidationContext<'_>,
+) -> anyhow::Result<Option<ValidationError>> {
+    Ok(taken(ctx.db, "cards", "number", number, ctx.id)
+        .await?
+        .then(|| ValidationError::CardNumberAlreadyExists(number.to_owned())))
+}
@@ -0,0 +1,26 @@
+use poem_openapi::{Enum, Object};
+
+#[derive(Enum, Clone, Copy, PartialEq)]
+#[oai(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Up,
+    Down,
+}
+
+/// Outcome of a probe, `down` when any check failed
+#[derive(Object)]
+#[oai(rename_all = "camelCase")]
+pub struct Health {
+    pub status: CheckStatus,
+    pub checks: Vec<Check>,
+}
+
+#[derive(Object)]
+#[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub duration_ms: f64,
+    /// Why the check failed
+    pub error: Option<String>,
+}
@@ -1,4 +1,6 @@
 pub mod bank;
 pub mod browse;
 pub mod card;
+pub mod health;
+pub mod seed;
 pub mod user;
@@ -0,0 +1,87 @@
+use bublik_macros::Validation;
+use clap::ValueEnum;
+use poem_openapi::{Enum, Object};
+use serde_json::Value;
+
+use crate::api::validation_error::ValidationError;
+
+#[derive(Enum, ValueEnum, Clone, Copy, Default, PartialEq)]
+#[oai(rename_all = "lowercase")]
+pub enum SeedMode {
+    /// Load fixtures only into empty tables
+    #[default]
+    Missing,
+    /// Clear all tables before loading fixtures
+    Reset,
+    /// Insert fixtures, overwriting rows with the same id
+    Upsert,
+}
+
+#[derive(Object, Validation)]
+#[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
+pub struct SeedRequest {
+    #[oai(default)]
+    pub mode: SeedMode,
+    /// Subdirectory of the data directory, the data directory itself when omitted
+    #[val(trim, pattern = r"^[A-Za-z0-9_-]+$")]
+    pub dataset: Option<String>,
+}
+
+#[derive(Object)]
+#[oai(rename_all = "camelCase")]
+pub struct SeedReport {
+    pub banks: SeedTable,
+    pub users: SeedTable,
+    pub cards: SeedTable,
+}
+
+/// Outcome of loading one fixture file
+#[derive(Object)]
+#[oai(rename_all = "camelCase")]
+pub struct SeedTable {
+    pub table: String,
+    /// Fixture file was present
+    pub found: bool,
+    /// Table was left untouched because it already had rows
+    pub skipped: bool,
+    pub loaded: u32,
+    pub rejected: Vec<SeedRejection>,
+}
+
+/// Failed check of a fixture row, rows failing several checks appear repeatedly
+#[derive(Object)]
+#[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
+pub struct SeedRejection {
+    pub id: u32,
+    pub field: Option<String>,
+    pub code: String,
+    pub parameters: Option<Vec<Option<Value>>>,
+}
+
+impl SeedTable {
+    pub fn new(table: &str) -> Self {
+        Self {
+            table: table.to_owned(),
+            found: false,
+            skipped: false,
+            loaded: 0,
+            rejected: Vec::new(),
+        }
+    }
+
+    pub fn reject(&mut self, id: u32, error: &ValidationError) {
+        self.rejected.push(SeedRejection {
+            id,
+            field: error.field(),
+            code: error.to_string(),
+            parameters: error.parameters(),
+        });
+    }
+    /// Number of rows with at least one rejection
+    pub fn rejected_rows(&self) -> usize {
+        let mut ids: Vec<_> = self.rejected.iter().map(|rejection| rejection.id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids.len()
+    }
+}
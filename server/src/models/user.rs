@@ -1,9 +1,21 @@
+use anyhow::{anyhow, Context};
 use bublik_macros::Validation;
 use chrono::NaiveDate;
 use int_enum::IntEnum;
 use poem_openapi::{Enum, Object};
 use serde::Deserialize;
-use sqlx::FromRow;
+use sqlx::{query_as, FromRow};
+
+use super::{
+    bank::bank_exists,
+    browse::{Conditions, Filter, Key, Keyset},
+};
+use crate::api::{
+    validation_error::ValidationError,
+    validation_rules::{age, referenced, taken, ValidationContext},
+};
+
+const WORKER_MIN_AGE: u32 = 18;
 
 #[derive(Object, Deserialize, FromRow)]
 #[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
@@ -19,10 +31,11 @@ pub struct User {
     pub birthday: NaiveDate,
     #[sqlx(try_from = "u32")]
     pub user_type: UserType,
+    pub bank_id: Option<u32>,
 }
 
 #[repr(u32)]
-#[derive(Enum, Deserialize, Clone, Copy, IntEnum)]
+#[derive(Debug, Enum, Deserialize, Clone, Copy, IntEnum)]
 pub enum UserType {
     Worker = 1,
     Manager = 2,
@@ -37,20 +50,249 @@ impl From<u32> for UserType {
 
 #[derive(Object, Validation)]
 #[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
-#[val(trim, length = "field_length")]
+#[val(
+    trim,
+    length = "field_length",
+    rule(path = "create_worker_age", codes(MinAge))
+)]
 pub struct CreateUser {
     pub first_name: String,
     pub last_name: String,
     #[val(
-        pattern = r"^([a-z0-9_+]([a-z0-9_+.]*[a-z0-9_+])?)@([a-z0-9]+([\-\.]{1}[a-z0-9]+)*\.[a-z]{2,6})"
+        pattern = r"^([a-z0-9_+]([a-z0-9_+.]*[a-z0-9_+])?)@([a-z0-9]+([\-\.]{1}[a-z0-9]+)*\.[a-z]{2,6})",
+        check(path = "email_available", codes(UserEmailAlreadyExists))
     )]
     pub email: String,
+    #[val(phone)]
     pub phone: String,
     #[oai(default = "NaiveDate::default")]
     pub birthday: NaiveDate,
     pub user_type: UserType,
+    /// Home bank
+    #[val(check(path = "bank_exists", codes(ReferenceNotExists)))]
+    pub bank_id: Option<u32>,
+}
+
+#[derive(Object, Validation)]
+#[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
+#[val(
+    trim,
+    length = "field_length",
+    check(path = "patch_worker_age", codes(MinAge))
+)]
+pub struct PatchUser {
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    #[val(
+        pattern = r"^([a-z0-9_+]([a-z0-9_+.]*[a-z0-9_+])?)@([a-z0-9]+([\-\.]{1}[a-z0-9]+)*\.[a-z]{2,6})",
+        check(path = "email_available", codes(UserEmailAlreadyExists))
+    )]
+    pub email: Option<String>,
+    #[val(phone)]
+    pub phone: Option<String>,
+    pub birthday: Option<NaiveDate>,
+    pub user_type: Option<UserType>,
+    #[val(check(path = "bank_exists", codes(ReferenceNotExists)))]
+    pub bank_id: Option<u32>,
+}
+
+/// Sortable by `id`, `firstName`, `lastName`, `email`, `phone`, `birthday` and `userType`
+#[derive(Object)]
+#[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
+pub struct UserFilter {
+    pub user_type: Option<UserType>,
+    /// Last name prefix
+    pub last_name: Option<String>,
+    /// Born on or after
+    pub birthday_from: Option<NaiveDate>,
+    /// Born on or before
+    pub birthday_to: Option<NaiveDate>,
+    pub bank_id: Option<u32>,
+}
+
+impl Filter for UserFilter {
+    const SORT_FIELDS: &'static [&'static str] = &[
+        "id",
+        "firstName",
+        "lastName",
+        "email",
+        "phone",
+        "birthday",
+        "userType",
+    ];
+
+    fn push_conditions(&self, conditions: &mut Conditions<'_, '_>) {
+        if let Some(user_type) = self.user_type {
+            conditions.push("userType = ", user_type as u32);
+        }
+        if let Some(last_name) = &self.last_name {
+            conditions.push_prefix("lastName", last_name);
+        }
+        if let Some(birthday_from) = self.birthday_from {
+            conditions.push("birthday >= ", birthday_from);
+        }
+        if let Some(birthday_to) = self.birthday_to {
+            conditions.push("birthday <= ", birthday_to);
+        }
+        if let Some(bank_id) = self.bank_id {
+            conditions.push("bankId = ", bank_id);
+        }
+    }
+}
+
+impl Keyset for User {
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn key(&self, field: &str) -> Key {
+        match field {
+            "firstName" => self.first_name.clone().into(),
+            "lastName" => self.last_name.clone().into(),
+            "email" => self.email.clone().into(),
+            "phone" => self.phone.clone().into(),
+            "birthday" => self.birthday.to_string().into(),
+            "userType" => (self.user_type as u32).into(),
+            _ => self.id.into(),
+        }
+    }
 }
 
 fn field_length() -> (usize, usize) {
     (3, 64)
 }
+
+fn create_worker_age(user: &CreateUser) -> Result<(), ValidationError> {
+    worker_age(user.user_type, user.birthday)
+}
+
+/// Checks the user as it is stored after the patch, so changing only the type or only the
+/// birthday cannot get around the rule
+async fn patch_worker_age(
+    user: &PatchUser,
+    ctx: &ValidationContext<'_>,
+) -> anyhow::Result<Option<ValidationError>> {
+    if user.user_type.is_none() && user.birthday.is_none() {
+        return Ok(None);
+    }
+    let Some(id) = ctx.id else {
+        return Ok(None);
+    };
+    // A missing user is answered with `EntityNotExists` by the handler
+    let Some((user_type, birthday)) =
+        query_as::<_, (u32, NaiveDate)>("SELECT userType, birthday FROM users WHERE id = ?")
+            .bind(id)
+            .fetch_optional(ctx.db)
+            .await
+            .context("get patched user")?
+    else {
+        return Ok(None);
+    };
+    let user_type = match user.user_type {
+        Some(user_type) => user_type,
+        None => UserType::from_int(user_type)
+            .map_err(|_| anyhow!("user {id} has unknown type {user_type}"))?,
+    };
+    Ok(worker_age(user_type, user.birthday.unwrap_or(birthday)).err())
+}
+
+/// Workers have to be adults, customers and managers are not restricted
+fn worker_age(user_type: UserType, birthday: NaiveDate) -> Result<(), ValidationError> {
+    if matches!(user_type, UserType::Worker) && age(birthday) < WORKER_MIN_AGE {
+        Err(ValidationError::MinAge {
+            field: "birthday",
+            min: WORKER_MIN_AGE,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+async fn email_available(
+    email: &str,
+    ctx: &ValidationContext<'_>,
+) -> anyhow::Result<Option<ValidationError>> {
+    Ok(taken(ctx.db, "users", "email", email, ctx.id)
+        .await?
+        .then(|| ValidationError::UserEmailAlreadyExists(email.to_owned())))
+}
+
+/// Check of fields referencing a user
+pub async fn user_exists(
+    id: &u32,
+    ctx: &ValidationContext<'_>,
+) -> anyhow::Result<Option<ValidationError>> {
+    referenced(ctx, "users", "User", *id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Days;
+    use sqlx::{query, sqlite::SqlitePoolOptions, Pool, Sqlite};
+
+    use super::*;
+    use crate::{api::validation_rules::today, db::migrate};
+
+    /// In-memory database holding adult worker 1 and minor customer 2
+    async fn database() -> Pool<Sqlite> {
+        // A single connection keeps the in-memory database alive
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        migrate::run(&db).await.unwrap();
+        query(
+            "INSERT INTO users (id, firstName, lastName, email, phone, birthday, userType) VALUES
+                (1, 'Adam', 'Nowak', 'adam@bublik.pl', '+48100200300', '1990-01-01', 1),
+                (2, 'Ola', 'Nowak', 'ola@bublik.pl', '+48100200301', ?, 3)",
+        )
+        .bind(minor_birthday())
+        .execute(&db)
+        .await
+        .unwrap();
+        db
+    }
+
+    fn minor_birthday() -> NaiveDate {
+        today() - Days::new(10 * 365)
+    }
+
+    fn patch(user_type: Option<UserType>, birthday: Option<NaiveDate>) -> PatchUser {
+        PatchUser {
+            first_name: None,
+            last_name: None,
+            email: None,
+            phone: None,
+            birthday,
+            user_type,
+            bank_id: None,
+        }
+    }
+
+    async fn check(db: &Pool<Sqlite>, id: u32, user: PatchUser) -> Option<ValidationError> {
+        let ctx = ValidationContext { db, id: Some(id) };
+        patch_worker_age(&user, &ctx).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn patch_worker_age_merges_stored_user() {
+        let db = database().await;
+        let minor = Some(minor_birthday());
+        let adult = NaiveDate::from_ymd_opt(1990, 1, 1);
+        let worker = Some(UserType::Worker);
+        let manager = Some(UserType::Manager);
+        assert!(matches!(
+            check(&db, 1, patch(None, minor)).await,
+            Some(ValidationError::MinAge { .. })
+        ));
+        assert!(matches!(
+            check(&db, 2, patch(worker, None)).await,
+            Some(ValidationError::MinAge { .. })
+        ));
+        assert!(check(&db, 2, patch(worker, adult)).await.is_none());
+        assert!(check(&db, 1, patch(manager, minor)).await.is_none());
+        assert!(check(&db, 2, patch(None, None)).await.is_none());
+        assert!(check(&db, 3, patch(worker, minor)).await.is_none());
+    }
+}